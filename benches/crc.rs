@@ -0,0 +1,55 @@
+use std::io::Cursor;
+use std::str::FromStr;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use png_rs::checksum::ChunkCrc;
+use png_rs::chunk::Chunk;
+use png_rs::chunk_type::ChunkType;
+use png_rs::png::Png;
+use png_rs::reader::ChunkReader;
+
+const SIZE: usize = 100 * 1024 * 1024;
+
+/// The previous implementation: copy the type and data into one buffer and
+/// build the CRC table on every call.
+fn copying_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let bytes: Vec<u8> = chunk_type.bytes().iter().chain(data.iter()).copied().collect();
+
+    Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&bytes)
+}
+
+fn crc(c: &mut Criterion) {
+    let chunk_type = ChunkType::from_str("IDAT").unwrap();
+    let data: Vec<u8> = (0..SIZE).map(|i| (i * 31 % 251) as u8).collect();
+
+    // A 100 MB file split into 1 MB IDAT chunks, as an encoder would write it.
+    let chunks = data.chunks(1024 * 1024).map(|piece| Chunk::new(chunk_type, piece.to_vec())).collect();
+    let file = Png::from_chunks(chunks).as_bytes();
+
+    let mut group = c.benchmark_group("crc");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SIZE as u64));
+
+    group.bench_function("copying", |b| b.iter(|| copying_crc(&chunk_type, &data)));
+    group.bench_function("incremental", |b| b.iter(|| Chunk::calculate_crc(&chunk_type, &data)));
+    group.bench_function("read_chunk", |b| {
+        b.iter(|| {
+            let mut reader = ChunkReader::new(Cursor::new(&file));
+            while reader.read_chunk().unwrap().is_some() {}
+        })
+    });
+    group.bench_function("skip_chunk", |b| {
+        b.iter(|| {
+            let mut reader = ChunkReader::new(Cursor::new(&file));
+            while reader.skip_chunk().unwrap().is_some() {}
+        })
+    });
+    group.bench_function("from_reader", |b| b.iter(|| ChunkCrc::from_reader(&chunk_type, data.as_slice()).unwrap()));
+
+    group.finish();
+}
+
+criterion_group!(benches, crc);
+criterion_main!(benches);
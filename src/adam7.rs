@@ -0,0 +1,106 @@
+use crate::ihdr::Ihdr;
+
+/// One of the seven passes of Adam7 interlacing (PNG spec, 8.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub x_start: u32,
+    pub y_start: u32,
+    pub x_step: u32,
+    pub y_step: u32,
+}
+
+pub const PASSES: [Pass; 7] = [
+    Pass { x_start: 0, y_start: 0, x_step: 8, y_step: 8 },
+    Pass { x_start: 4, y_start: 0, x_step: 8, y_step: 8 },
+    Pass { x_start: 0, y_start: 4, x_step: 4, y_step: 8 },
+    Pass { x_start: 2, y_start: 0, x_step: 4, y_step: 4 },
+    Pass { x_start: 0, y_start: 2, x_step: 2, y_step: 4 },
+    Pass { x_start: 1, y_start: 0, x_step: 2, y_step: 2 },
+    Pass { x_start: 0, y_start: 1, x_step: 1, y_step: 2 },
+];
+
+impl Pass {
+    /// Width and height of the reduced image this pass holds for a full
+    /// image of `width` by `height` pixels. Either may be zero, in which
+    /// case the pass is absent from the data stream.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let reduce = |length: u32, start: u32, step: u32| {
+            if length > start {
+                (length - start).div_ceil(step)
+            } else {
+                0
+            }
+        };
+
+        (
+            reduce(width, self.x_start, self.x_step),
+            reduce(height, self.y_start, self.y_step),
+        )
+    }
+
+    /// Copies the pixels of this pass's reduced image into their positions
+    /// in the full image `data`.
+    pub(crate) fn scatter(&self, ihdr: &Ihdr, pass_data: &[u8], data: &mut [u8]) {
+        let (pass_width, pass_height) = self.size(ihdr.width, ihdr.height);
+        let pass_row_bytes = ihdr.row_bytes(pass_width);
+        let row_bytes = ihdr.row_bytes(ihdr.width);
+        let bits = ihdr.bits_per_pixel();
+
+        for py in 0..pass_height as usize {
+            let y = self.y_start as usize + py * self.y_step as usize;
+            let src = &pass_data[py * pass_row_bytes..(py + 1) * pass_row_bytes];
+            let dst = &mut data[y * row_bytes..(y + 1) * row_bytes];
+
+            for px in 0..pass_width as usize {
+                let x = self.x_start as usize + px * self.x_step as usize;
+                copy_pixel(bits, src, px, dst, x);
+            }
+        }
+    }
+}
+
+/// Copies pixel `from` of row `src` to pixel `to` of row `dst`, for pixels
+/// of `bits` bits packed most significant bit first.
+fn copy_pixel(bits: usize, src: &[u8], from: usize, dst: &mut [u8], to: usize) {
+    if bits >= 8 {
+        let bytes = bits / 8;
+        dst[to * bytes..(to + 1) * bytes].copy_from_slice(&src[from * bytes..(from + 1) * bytes]);
+        return;
+    }
+
+    let mask = (1u8 << bits) - 1;
+    let src_shift = 8 - bits - (from * bits) % 8;
+    let dst_shift = 8 - bits - (to * bits) % 8;
+    let value = (src[from * bits / 8] >> src_shift) & mask;
+
+    let byte = &mut dst[to * bits / 8];
+    *byte = (*byte & !(mask << dst_shift)) | (value << dst_shift);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_sizes() {
+        let sizes: Vec<(u32, u32)> = PASSES.iter().map(|pass| pass.size(8, 8)).collect();
+
+        assert_eq!(sizes, vec![(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]);
+    }
+
+    #[test]
+    fn test_pass_sizes_small_image() {
+        let sizes: Vec<(u32, u32)> = PASSES.iter().map(|pass| pass.size(1, 1)).collect();
+
+        assert_eq!(sizes, vec![(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_copy_sub_byte_pixel() {
+        let src = [0b0110_0000];
+        let mut dst = [0b1111_1111];
+        copy_pixel(2, &src, 1, &mut dst, 3);
+
+        assert_eq!(dst, [0b1111_1110]);
+    }
+}
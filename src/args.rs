@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "png-rs", version, about = "Hide and recover messages in PNG files")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Store a message in a new chunk of the given type
+    Encode(EncodeArgs),
+    /// Print the message stored in the first chunk of the given type
+    Decode(DecodeArgs),
+    /// Hide a message in the low bits of the pixels and re-encode the image;
+    /// the key is read from PNG_RS_STEGO_KEY or prompted for
+    Hide(HideArgs),
+    /// Print the message hidden in the pixels by hide, with the same key
+    Reveal(RevealArgs),
+    /// Remove the first chunk of the given type
+    Remove(RemoveArgs),
+    /// List every chunk in the file
+    Print(PrintArgs),
+    /// Losslessly recompress the file, rewriting it only if it gets smaller
+    Optimize(OptimizeArgs),
+    /// Report every chunk and check the file against the spec
+    Check(CheckArgs),
+    /// Fix CRCs, chunk lengths, a missing IEND and trailing junk
+    Repair(RepairArgs),
+    /// Generate an X25519 key pair for --recipient and --identity
+    Keygen(KeygenArgs),
+    /// Sign the image data and chosen ancillary chunks with an Ed25519 key
+    Sign(SignArgs),
+    /// Check the signature against one or more verifying keys
+    Verify(VerifyArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct EncodeArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    pub message: String,
+    /// Write the result here instead of overwriting the input file
+    pub output: Option<PathBuf>,
+    /// Encrypt the message with a passphrase, read from PNG_RS_PASSPHRASE or
+    /// prompted for
+    #[arg(long, conflicts_with = "recipients")]
+    pub encrypt: bool,
+    /// Encrypt the message to this public key; repeat for several recipients
+    #[arg(short, long = "recipient", value_name = "PUBLIC_KEY")]
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DecodeArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+    /// Identity file to decrypt a message sent to recipients; may be repeated
+    #[arg(short, long = "identity", value_name = "FILE")]
+    pub identities: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct HideArgs {
+    pub file_path: PathBuf,
+    pub message: String,
+    /// Write the result here instead of overwriting the input file
+    pub output: Option<PathBuf>,
+    /// Low bits of each color sample to use, at most half the bit depth
+    #[arg(short, long, default_value_t = 1)]
+    pub bits: u8,
+    /// Encrypt the message with a passphrase, read from PNG_RS_PASSPHRASE or
+    /// prompted for
+    #[arg(long, conflicts_with = "recipients")]
+    pub encrypt: bool,
+    /// Encrypt the message to this public key; repeat for several recipients
+    #[arg(short, long = "recipient", value_name = "PUBLIC_KEY")]
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RevealArgs {
+    pub file_path: PathBuf,
+    /// Low bits of each color sample the message was hidden in
+    #[arg(short, long, default_value_t = 1)]
+    pub bits: u8,
+    /// Identity file to decrypt a message sent to recipients; may be repeated
+    #[arg(short, long = "identity", value_name = "FILE")]
+    pub identities: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: String,
+}
+
+#[derive(Debug, Args)]
+pub struct PrintArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct OptimizeArgs {
+    pub file_path: PathBuf,
+    /// Write the result here instead of overwriting the input file
+    pub output: Option<PathBuf>,
+    /// Also try brute-force filter selection, which is much slower
+    #[arg(long)]
+    pub brute_force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    /// Write the result here instead of overwriting the input file
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Write the identity file here instead of printing it
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Generate an Ed25519 signing key for sign and verify instead
+    #[arg(long)]
+    pub sign: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SignArgs {
+    pub file_path: PathBuf,
+    /// Write the result here instead of overwriting the input file
+    pub output: Option<PathBuf>,
+    /// Signing key file made by keygen --sign
+    #[arg(short, long, value_name = "FILE")]
+    pub key: PathBuf,
+    /// Ancillary chunk type to sign as well as IHDR, PLTE and IDAT; may be repeated
+    #[arg(short, long = "chunk", value_name = "CHUNK_TYPE")]
+    pub chunk_types: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    /// Verifying key to accept; may be repeated
+    #[arg(short, long = "key", value_name = "VERIFYING_KEY", required = true)]
+    pub keys: Vec<String>,
+}
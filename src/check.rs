@@ -0,0 +1,403 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{Ihdr, InterlaceMethod};
+use crate::ordering;
+use crate::plte::Plte;
+use crate::png::Png;
+use crate::secret;
+use crate::signature::{self, SignatureChunk};
+use crate::text::TextChunk;
+use crate::trns::Trns;
+
+/// Longest text shown for a text chunk.
+const TEXT_LENGTH: usize = 60;
+
+/// Everything `check` found, in file order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+    /// The header, if an `IHDR` chunk could be read.
+    pub ihdr: Option<Ihdr>,
+    pub chunk_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Chunk(ChunkEntry),
+    /// A problem with the file as a whole: its signature, chunk framing,
+    /// chunk order or image data.
+    Error(String),
+}
+
+/// One chunk with its property bits, CRC status and decoded contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkEntry {
+    pub chunk_type: ChunkType,
+    pub offset: u64,
+    pub length: u32,
+    pub stored_crc: u32,
+    pub computed_crc: u32,
+    /// The fields of a known chunk as a line of text.
+    pub description: Option<String>,
+    /// Problems with the chunk other than its CRC.
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    /// Number of problems found, counting a CRC mismatch as one.
+    pub fn error_count(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Chunk(chunk) => chunk.errors.len() + usize::from(!chunk.crc_matches()),
+                Entry::Error(_) => 1,
+            })
+            .sum()
+    }
+
+    /// Whether the file is a valid PNG, which is the exit status of the
+    /// `check` command.
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0 && self.ihdr.is_some()
+    }
+
+    /// The last line of the report, `OK: ...` or `ERROR: ...`, for the file
+    /// called `name`.
+    pub fn summary(&self, name: impl Display) -> String {
+        match (self.error_count(), &self.ihdr) {
+            (0, Some(ihdr)) => format!(
+                "OK: {} ({} x {}, {}-bit {}, {} chunks)",
+                name, ihdr.width, ihdr.height, ihdr.bit_depth, ihdr.color_type, self.chunk_count,
+            ),
+            (errors, _) => format!("ERROR: {} ({} {})", name, errors, if errors == 1 { "error" } else { "errors" }),
+        }
+    }
+}
+
+impl ChunkEntry {
+    pub fn crc_matches(&self) -> bool {
+        self.stored_crc == self.computed_crc
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Chunk(chunk) => write!(f, "{}", chunk),
+            Entry::Error(reason) => write!(f, "  {}", reason),
+        }
+    }
+}
+
+impl Display for ChunkEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let chunk_type = &self.chunk_type;
+        let crc_status = match self.crc_matches() {
+            true => String::from("CRC ok"),
+            false => format!("CRC error (stored {:#010x}, computed {:#010x})", self.stored_crc, self.computed_crc),
+        };
+
+        write!(
+            f,
+            "chunk {} at offset {:#07x}, length {} [{}, {}, {}, {}], {}",
+            chunk_type,
+            self.offset,
+            self.length,
+            if chunk_type.is_critical() { "critical" } else { "ancillary" },
+            if chunk_type.is_public() { "public" } else { "private" },
+            if chunk_type.is_reserved_bit_valid() { "reserved bit ok" } else { "reserved bit set" },
+            if chunk_type.is_safe_to_copy() { "safe to copy" } else { "unsafe to copy" },
+            crc_status,
+        )?;
+
+        if let Some(description) = &self.description {
+            write!(f, "\n    {}", description)?;
+        }
+        for error in &self.errors {
+            write!(f, "\n    error: {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads every chunk of `bytes` without giving up on CRC errors or invalid
+/// contents, then checks chunk ordering and, if nothing else is wrong, the
+/// image data.
+pub fn check(bytes: &[u8]) -> Report {
+    let mut entries = Vec::new();
+    let mut chunks = Vec::new();
+    let mut ihdr = None;
+    let mut plte = None;
+
+    // Cleared when the chunk structure is too broken to read any further.
+    let mut complete = bytes.starts_with(&Png::STANDARD_HEADER);
+    if !complete {
+        entries.push(Entry::Error(String::from("invalid PNG signature")));
+    }
+
+    let mut offset = Png::STANDARD_HEADER.len();
+    while complete && offset < bytes.len() {
+        let (chunk, used) = match Chunk::parse_unverified(&bytes[offset..]) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                entries.push(Entry::Error(e.offset_by(offset as u64).to_string()));
+                complete = false;
+                break;
+            },
+        };
+
+        let chunk_type = *chunk.chunk_type();
+        let mut errors = Vec::new();
+        if !chunk_type.is_reserved_bit_valid() {
+            errors.push(String::from("reserved bit is set"));
+        }
+
+        let description = describe(&chunk, &mut ihdr, &mut plte).unwrap_or_else(|e| {
+            errors.push(e.to_string());
+            None
+        });
+
+        entries.push(Entry::Chunk(ChunkEntry {
+            chunk_type,
+            offset: offset as u64,
+            length: chunk.length(),
+            stored_crc: chunk.crc(),
+            computed_crc: Chunk::calculate_crc(&chunk_type, chunk.data()),
+            description,
+            errors,
+        }));
+
+        chunks.push(chunk);
+        offset += used;
+    }
+
+    if complete {
+        for violation in ordering::validate(&chunks) {
+            entries.push(Entry::Error(violation.to_string()));
+        }
+    }
+
+    let chunk_count = chunks.len();
+    let mut report = Report { entries, ihdr, chunk_count };
+
+    if report.error_count() == 0 {
+        if let Err(e) = Png::from_chunks(chunks).decode() {
+            report.entries.push(Entry::Error(format!("image data: {}", e)));
+        }
+    }
+
+    report
+}
+
+/// Decodes the fields of known chunks into a line of text. `ihdr` and
+/// `plte` carry the header and palette seen so far.
+fn describe(chunk: &Chunk, ihdr: &mut Option<Ihdr>, plte: &mut Option<Plte>) -> Result<Option<String>> {
+    let chunk_type = *chunk.chunk_type();
+    let invalid = |reason: String| PngError::InvalidChunkData { chunk_type, reason };
+    let fixed = |length: usize| match chunk.data().len() == length {
+        true => Ok(chunk.data()),
+        false => Err(invalid(format!("expected {} bytes, got {}", length, chunk.data().len()))),
+    };
+
+    let description = match &chunk_type.bytes() {
+        b"IHDR" => {
+            let header = Ihdr::try_from(chunk)?;
+            header.validate()?;
+            *ihdr = Some(header);
+
+            format!(
+                "{} x {} image, {}-bit {}, {}",
+                header.width,
+                header.height,
+                header.bit_depth,
+                header.color_type,
+                match header.interlace_method {
+                    InterlaceMethod::None => "non-interlaced",
+                    InterlaceMethod::Adam7 => "Adam7 interlaced",
+                },
+            )
+        },
+        b"PLTE" => {
+            let palette = Plte::try_from(chunk)?;
+            if let Some(ihdr) = ihdr {
+                palette.validate(ihdr)?;
+            }
+            let description = format!("{} palette entries", palette.len());
+            *plte = Some(palette);
+
+            description
+        },
+        b"tRNS" => {
+            let ihdr = ihdr.as_ref().ok_or_else(|| invalid(String::from("comes before IHDR")))?;
+            let trns = Trns::from_chunk(chunk, ihdr)?;
+            trns.validate(ihdr, plte.as_ref())?;
+
+            match trns {
+                Trns::Gray(gray) => format!("transparent gray {}", gray),
+                Trns::Rgb([r, g, b]) => format!("transparent color {} {} {}", r, g, b),
+                Trns::Indexed(alpha) => format!("{} palette alpha values", alpha.len()),
+            }
+        },
+        b"tEXt" | b"zTXt" | b"iTXt" => {
+            let text_chunk = TextChunk::try_from(chunk)?;
+            let text = text_chunk.text();
+            let shown: String = text.chars().take(TEXT_LENGTH).collect();
+            let ellipsis = if shown.len() < text.len() { "..." } else { "" };
+
+            format!("{}: {:?}{}", text_chunk.keyword(), shown, ellipsis)
+        },
+        b"gAMA" => {
+            let data = fixed(4)?;
+            format!("gamma {:.5}", u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64 / 100_000.0)
+        },
+        b"sRGB" => {
+            let intent = match fixed(1)?[0] {
+                0 => "perceptual",
+                1 => "relative colorimetric",
+                2 => "saturation",
+                3 => "absolute colorimetric",
+                intent => return Err(invalid(format!("unknown rendering intent {}", intent))),
+            };
+            format!("sRGB, {} rendering intent", intent)
+        },
+        b"pHYs" => {
+            let data = fixed(9)?;
+            let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+            match data[8] {
+                0 => format!("{} x {} pixel aspect ratio", x, y),
+                1 => format!("{} x {} pixels per meter", x, y),
+                unit => return Err(invalid(format!("unknown unit {}", unit))),
+            }
+        },
+        b"tIME" => {
+            let data = fixed(7)?;
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                u16::from_be_bytes([data[0], data[1]]),
+                data[2],
+                data[3],
+                data[4],
+                data[5],
+                data[6],
+            )
+        },
+        b"IEND" => {
+            fixed(0)?;
+            return Ok(None);
+        },
+        b"IDAT" => return Ok(None),
+        // Unknown critical chunks are reported by `ordering::validate`.
+        _ if chunk_type.is_critical() => return Ok(None),
+        b"siGn" => {
+            let signature = SignatureChunk::try_from(chunk)?;
+            let covered: Vec<String> = signature::COVERED
+                .iter()
+                .chain(&signature.chunk_types)
+                .map(ChunkType::to_string)
+                .collect();
+
+            format!("Ed25519 signature by key {} over {}", signature.key_id, covered.join(", "))
+        },
+        _ if secret::is_encrypted(chunk.data()) => String::from("encrypted message"),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(description))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::encoder::Encoder;
+    use crate::ihdr::ColorType;
+    use crate::ordering::InsertPosition;
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut png = Encoder::new(2, 2, ColorType::Grayscale, 8).unwrap().encode(&[0, 64, 128, 255]).unwrap();
+        let iend = png.remove_chunk("IEND").unwrap();
+        png.append_chunk(TextChunk::new("Title", "check").unwrap().to_chunk().unwrap());
+        png.append_chunk(iend);
+
+        png.as_bytes()
+    }
+
+    fn chunk_entries(report: &Report) -> Vec<&ChunkEntry> {
+        report
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Chunk(chunk) => Some(chunk),
+                Entry::Error(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_valid() {
+        let report = check(&testing_bytes());
+
+        assert!(report.is_ok());
+        assert_eq!(report.error_count(), 0);
+        assert_eq!(report.summary("a.png"), "OK: a.png (2 x 2, 8-bit grayscale, 4 chunks)");
+
+        let chunks = chunk_entries(&report);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0].offset, 8);
+        assert_eq!(chunks[0].description.as_deref(), Some("2 x 2 image, 8-bit grayscale, non-interlaced"));
+        assert_eq!(chunks[2].description.as_deref(), Some("Title: \"check\""));
+        assert!(chunks[2].to_string().starts_with("chunk tEXt at offset "));
+    }
+
+    #[test]
+    fn test_check_crc_error() {
+        let mut bytes = testing_bytes();
+        // Last byte of the IHDR CRC.
+        bytes[32] ^= 0xff;
+
+        let report = check(&bytes);
+
+        assert!(!report.is_ok());
+        assert_eq!(report.error_count(), 1);
+        assert_eq!(report.summary("a.png"), "ERROR: a.png (1 error)");
+        assert!(!chunk_entries(&report)[0].crc_matches());
+        assert!(chunk_entries(&report)[0].to_string().contains("CRC error (stored"));
+    }
+
+    #[test]
+    fn test_check_invalid_contents() {
+        let mut png = Png::try_from(testing_bytes().as_slice()).unwrap();
+        png.insert_chunk(Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![9]), InsertPosition::BeforeFirstIdat)
+            .unwrap();
+
+        let report = check(&png.as_bytes());
+
+        assert_eq!(report.error_count(), 1);
+        assert_eq!(chunk_entries(&report)[1].errors.len(), 1);
+        assert_eq!(report.summary("a.png"), "ERROR: a.png (1 error)");
+    }
+
+    #[test]
+    fn test_check_unreadable() {
+        let report = check(b"not a png");
+
+        assert!(!report.is_ok());
+        assert_eq!(report.entries, vec![Entry::Error(String::from("invalid PNG signature"))]);
+        assert_eq!(report.summary("a.png"), "ERROR: a.png (1 error)");
+
+        let mut bytes = testing_bytes();
+        bytes.truncate(bytes.len() - 6);
+        let report = check(&bytes);
+
+        assert!(!report.is_ok());
+        assert!(matches!(report.entries.last(), Some(Entry::Error(_))));
+        assert_eq!(report.summary("a.png"), "ERROR: a.png (1 error)");
+    }
+}
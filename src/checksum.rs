@@ -0,0 +1,89 @@
+use std::io::{self, Read, Write};
+
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+
+use crate::chunk_type::ChunkType;
+
+/// The CRC-32 used by PNG (PNG spec, 5.5), built once at compile time.
+static CRC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// The PNG CRC-32 of `data` on its own, for checksums outside chunks.
+pub fn crc32(data: &[u8]) -> u32 {
+    CRC_32.checksum(data)
+}
+
+/// Incremental CRC of a chunk, fed its type and then its data in pieces of
+/// any size. Nothing is allocated, so data can be checked as it streams
+/// past, for example with `io::copy`.
+#[derive(Clone)]
+pub struct ChunkCrc {
+    digest: Digest<'static, u32>,
+}
+
+impl ChunkCrc {
+    pub fn new(chunk_type: &ChunkType) -> Self {
+        let mut digest = CRC_32.digest();
+        digest.update(&chunk_type.bytes());
+
+        Self { digest }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.digest.update(data);
+    }
+
+    pub fn finalize(self) -> u32 {
+        self.digest.finalize()
+    }
+
+    /// CRC of a chunk whose data is read from `reader` until it ends.
+    /// Returns the CRC and the number of data bytes.
+    pub fn from_reader<R: Read>(chunk_type: &ChunkType, mut reader: R) -> io::Result<(u32, u64)> {
+        let mut crc = Self::new(chunk_type);
+        let length = io::copy(&mut reader, &mut crc)?;
+
+        Ok((crc.finalize(), length))
+    }
+}
+
+impl Write for ChunkCrc {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::chunk::Chunk;
+
+    #[test]
+    fn test_chunk_crc_matches_chunk() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!";
+
+        let mut crc = ChunkCrc::new(&chunk_type);
+        for piece in data.chunks(5) {
+            crc.update(piece);
+        }
+
+        assert_eq!(crc.finalize(), 2882656334);
+        assert_eq!(Chunk::new(chunk_type, data.to_vec()).crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_chunk_crc_from_reader() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!";
+
+        assert_eq!(ChunkCrc::from_reader(&chunk_type, &data[..]).unwrap(), (2882656334, 42));
+        assert_eq!(ChunkCrc::from_reader(&ChunkType::IEND, io::empty()).unwrap(), (0xae42_6082, 0));
+    }
+}
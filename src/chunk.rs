@@ -1,13 +1,12 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
-use crc::{Crc, CRC_32_ISO_HDLC};
-
-use crate::{Result, Error};
+use crate::{PngError, Result};
+use crate::checksum::ChunkCrc;
 use crate::chunk_type::ChunkType;
 
-#[derive(Debug)]
-struct Chunk {
+#[derive(Debug, Clone)]
+pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
@@ -15,36 +14,19 @@ struct Chunk {
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = Error;
+    type Error = PngError;
 
     fn try_from(data: &[u8]) -> Result<Self> {
-        if data.len() < Self::DATA_BYTES {
-            return Err(format!("Length of data is too short. Expected {}, got {}", Self::DATA_BYTES, data.len()).into());
-        }
-
-        let (length_bytes, rest) = data.split_at(4);
-        let length = u32::from_be_bytes(length_bytes.try_into()?);
-
-        let (chunk_type_bytes, rest) = rest.split_at(4);
-        let chunk_type = ChunkType::try_from(
-            <&[u8] as TryInto<[u8; 4]>>::try_into(chunk_type_bytes)?
-        )?;
+        let (chunk, used) = Self::parse(data)?;
 
-        let (data_bytes, crc_bytes) = rest.split_at(rest.len() - 4);
-        let data = data_bytes.to_vec();
-
-        let crc = u32::from_be_bytes(crc_bytes.try_into()?);
-
-        if crc != Self::calculate_crc(&chunk_type, &data) {
-            return Err(String::from("CRC is invalid").into());
+        if used != data.len() {
+            return Err(PngError::TrailingBytes {
+                count: (data.len() - used) as u64,
+                offset: Some(used as u64),
+            });
         }
 
-        Ok(Chunk {
-            length,
-            chunk_type,
-            data,
-            crc,
-        })
+        Ok(chunk)
     }
 }
 
@@ -60,7 +42,9 @@ impl Chunk {
     pub const CRC_BYTES: usize = 4;
     
     pub const DATA_BYTES: usize = Self::LENGTH_BYTES + Self::CHUNK_TYPE_BYTES + Self::CRC_BYTES;
-    
+
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
         let length = data.len() as u32;
 
@@ -74,6 +58,19 @@ impl Chunk {
         }
     }
 
+    /// Parses the chunk at the start of `data` and returns it together with
+    /// the number of bytes it occupied, so the caller can continue with the
+    /// rest of the slice.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+        ChunkRef::parse(data).map(|(chunk, used)| (chunk.to_chunk(), used))
+    }
+
+    /// Like `parse`, but keeps the stored CRC without checking it. Use
+    /// `crc_matches` to find out whether it is right.
+    pub fn parse_unverified(data: &[u8]) -> Result<(Self, usize)> {
+        ChunkRef::parse_unverified(data).map(|(chunk, used)| (chunk.to_chunk(), used))
+    }
+
     pub fn length(&self) -> u32 {
         self.length
     }
@@ -90,28 +87,181 @@ impl Chunk {
         self.crc
     }
 
+    /// Whether the stored CRC is the one computed over the chunk.
+    pub fn crc_matches(&self) -> bool {
+        self.crc == Self::calculate_crc(&self.chunk_type, &self.data)
+    }
+
     pub fn data_as_string(&self) -> Result<String> {
         Ok(String::from_utf8_lossy(&self.data).into_owned())
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        todo!()
-    }
-
-    pub fn calculate_crc(chunk_type: &ChunkType, data: &Vec<u8>) -> u32 {
-        let crc_bytes: Vec<u8> = chunk_type
-            .bytes()
+        self.length
+            .to_be_bytes()
             .iter()
-            .chain(data.iter())
+            .chain(self.chunk_type.bytes().iter())
+            .chain(self.data.iter())
+            .chain(self.crc.to_be_bytes().iter())
             .copied()
-            .collect();
-         Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&crc_bytes)
+            .collect()
+    }
+
+    /// CRC over the chunk type and data, computed without copying them.
+    pub fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut crc = ChunkCrc::new(chunk_type);
+        crc.update(data);
+        crc.finalize()
+    }
+}
+
+/// A chunk borrowed from the buffer it was parsed from, such as a whole
+/// file read into memory or memory-mapped. Nothing is copied until
+/// `to_chunk` is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    length: u32,
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses the chunk at the start of `data` as `Chunk::parse` does,
+    /// borrowing its data.
+    pub fn parse(data: &'a [u8]) -> Result<(Self, usize)> {
+        let (chunk, used) = Self::parse_unverified(data)?;
+
+        if !chunk.crc_matches() {
+            return Err(PngError::CrcMismatch {
+                chunk_type: chunk.chunk_type,
+                expected: Chunk::calculate_crc(&chunk.chunk_type, chunk.data),
+                actual: chunk.crc,
+                offset: Some((used - Chunk::CRC_BYTES) as u64),
+            });
+        }
+
+        Ok((chunk, used))
+    }
+
+    /// Like `parse`, but keeps the stored CRC without checking it.
+    pub fn parse_unverified(data: &'a [u8]) -> Result<(Self, usize)> {
+        let truncated = |expected: usize| PngError::Truncated {
+            expected: expected as u64,
+            actual: data.len() as u64,
+            offset: Some(0),
+        };
+
+        let (length_bytes, rest) = data
+            .split_first_chunk::<{ Chunk::LENGTH_BYTES }>()
+            .ok_or_else(|| truncated(Chunk::DATA_BYTES))?;
+        let length = u32::from_be_bytes(*length_bytes);
+
+        if length > Chunk::MAX_LENGTH {
+            return Err(PngError::InvalidLength { length: length as u64, offset: Some(0) });
+        }
+
+        let used = Chunk::DATA_BYTES + length as usize;
+        if data.len() < used {
+            return Err(truncated(used));
+        }
+
+        let (chunk_type_bytes, rest) = rest
+            .split_first_chunk::<{ Chunk::CHUNK_TYPE_BYTES }>()
+            .ok_or_else(|| truncated(used))?;
+        let chunk_type = ChunkType::try_from(*chunk_type_bytes)
+            .map_err(|_| PngError::InvalidChunkType {
+                bytes: chunk_type_bytes.to_vec(),
+                offset: Some(Chunk::LENGTH_BYTES as u64),
+            })?;
+
+        let (data, rest) = rest.split_at(length as usize);
+
+        let (crc_bytes, _) = rest
+            .split_first_chunk::<{ Chunk::CRC_BYTES }>()
+            .ok_or_else(|| truncated(used))?;
+        let crc = u32::from_be_bytes(*crc_bytes);
+
+        Ok((Self { length, chunk_type, data, crc }, used))
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn crc_matches(&self) -> bool {
+        self.crc == Chunk::calculate_crc(&self.chunk_type, self.data)
+    }
+
+    /// Copies the data into an owned chunk, keeping the stored CRC.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            length: self.length,
+            chunk_type: self.chunk_type,
+            data: self.data.to_vec(),
+            crc: self.crc,
+        }
+    }
+}
+
+/// Iterates over the chunks in a buffer without copying them. Stops after
+/// the first error, which carries its offset in the buffer.
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> ChunkRefs<'a> {
+    /// Reads chunks from `offset` to the end of `bytes`.
+    pub fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset, failed: false }
+    }
+
+    /// Offset of the next chunk.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        match ChunkRef::parse(&self.bytes[self.offset..]) {
+            Ok((chunk, used)) => {
+                self.offset += used;
+                Some(Ok(chunk))
+            },
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e.offset_by(self.offset as u64)))
+            },
+        }
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
-    use::std::str::FromStr;
+    use std::str::FromStr;
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -214,6 +364,86 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_ref() {
+        let bytes = testing_chunk().as_bytes();
+        let (chunk, used) = ChunkRef::parse(&bytes).unwrap();
+
+        assert_eq!(used, bytes.len());
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert!(std::ptr::eq(chunk.data(), &bytes[8..50]));
+        assert_eq!(chunk.to_chunk().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_chunk_refs() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()).as_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+
+        let mut chunks = ChunkRefs::new(&bytes, 0);
+
+        assert_eq!(chunks.next().unwrap().unwrap().length(), 42);
+        assert_eq!(chunks.next().unwrap().unwrap().chunk_type().to_string(), "IEND");
+        assert!(matches!(chunks.next(), Some(Err(PngError::Truncated { offset: Some(66), .. }))));
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_unverified() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let (chunk, used) = Chunk::parse_unverified(&bytes).unwrap();
+
+        assert_eq!(used, bytes.len());
+        assert_eq!(chunk.crc(), 2882656334 ^ 1);
+        assert!(!chunk.crc_matches());
+        assert!(testing_chunk().crc_matches());
+    }
+
+    #[test]
+    fn test_chunk_length_mismatch() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let mut chunk_data = Chunk::new(chunk_type, b"This is where your secret message will be!".to_vec()).as_bytes();
+        chunk_data[3] = 41;
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    fn test_chunk_length_too_large() {
+        let chunk_data: Vec<u8> = (Chunk::MAX_LENGTH + 1)
+            .to_be_bytes()
+            .iter()
+            .chain("RuSt".as_bytes())
+            .chain(&[0; 4])
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    fn test_chunk_trailing_bytes() {
+        let chunk = testing_chunk();
+        let mut chunk_data = chunk.as_bytes();
+        chunk_data.extend_from_slice(&[1, 2, 3]);
+
+        assert!(Chunk::try_from(chunk_data.as_ref()).is_err());
+
+        let (parsed, used) = Chunk::parse(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(used, chunk_data.len() - 3);
+        assert_eq!(parsed.crc(), chunk.crc());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -3,42 +3,35 @@ use std::fmt::{Display, Formatter};
 use std::convert::TryFrom;
 use std::str::FromStr;
 
-use crate::{Result, Error};
+use crate::{PngError, Result};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = Error;
+    type Error = PngError;
 
     fn try_from(bytes: [u8; 4]) -> Result<Self> {
         if bytes.iter().all(|&b| b.is_ascii_alphabetic()) {
             Ok(Self { bytes })
         } else {
-            Err(format!("Invalid chunk type: {:?}", bytes).into())
+            Err(PngError::InvalidChunkType { bytes: bytes.to_vec(), offset: None })
         }
     }
 }
 
 impl FromStr for ChunkType {
-    type Err = Error;
+    type Err = PngError;
     
     fn from_str(string: &str) -> Result<Self> {
-        match (string.is_ascii(), string.len() == 4) {
-            (true, true) => {
-                let mut bytes: [u8; 4] = [0; 4];
-
-                for (i, c) in string.chars().enumerate() {
-                    bytes[i] = c as u8;
-                }
-
-                Ok(Self { bytes })
-            },
-            (false, _) => Err(format!("Invalid string length. Expected 4, got {:?}", string.len()).into()),
-            (_, false) => Err(String::from("The string contains non-ascii characters").into())
-        }
+        let bytes: [u8; 4] = string
+            .as_bytes()
+            .try_into()
+            .map_err(|_| PngError::InvalidChunkType { bytes: string.as_bytes().to_vec(), offset: None })?;
+
+        Self::try_from(bytes)
     }
 }
 
@@ -49,6 +42,20 @@ impl Display for ChunkType {
 }
 
 impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType { bytes: *b"IHDR" };
+    pub const PLTE: ChunkType = ChunkType { bytes: *b"PLTE" };
+    pub const IDAT: ChunkType = ChunkType { bytes: *b"IDAT" };
+    pub const IEND: ChunkType = ChunkType { bytes: *b"IEND" };
+    pub const TRNS: ChunkType = ChunkType { bytes: *b"tRNS" };
+    pub const BKGD: ChunkType = ChunkType { bytes: *b"bKGD" };
+    pub const SBIT: ChunkType = ChunkType { bytes: *b"sBIT" };
+    pub const HIST: ChunkType = ChunkType { bytes: *b"hIST" };
+    pub const TEXT: ChunkType = ChunkType { bytes: *b"tEXt" };
+    pub const ZTXT: ChunkType = ChunkType { bytes: *b"zTXt" };
+    pub const ITXT: ChunkType = ChunkType { bytes: *b"iTXt" };
+    /// Private, ancillary and safe to copy; see `signature::sign`.
+    pub const SIGNATURE: ChunkType = ChunkType { bytes: *b"siGn" };
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
@@ -89,6 +96,13 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_from_str() {
+        assert!(ChunkType::from_str("Ru1t").is_err());
+        assert!(ChunkType::from_str("RuStt").is_err());
+        assert!(ChunkType::from_str("Ruś").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_is_critical() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -0,0 +1,332 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use png_rs::{PngError, Result};
+use png_rs::check;
+use png_rs::chunk::Chunk;
+use png_rs::chunk_type::ChunkType;
+use png_rs::optimize::Optimizer;
+use png_rs::ordering::InsertPosition;
+use png_rs::png::Png;
+use png_rs::recipient::{Identity, Recipient};
+use png_rs::repair;
+use png_rs::secret::{self, Scheme};
+use png_rs::signature::{self, SigningKey, VerifyingKey};
+use png_rs::stego;
+
+use zeroize::Zeroizing;
+
+use crate::args::{
+    CheckArgs, DecodeArgs, EncodeArgs, HideArgs, KeygenArgs, OptimizeArgs, PrintArgs, RemoveArgs, RepairArgs,
+    RevealArgs, SignArgs, VerifyArgs,
+};
+
+/// Environment variable holding the passphrase, checked before prompting.
+const PASSPHRASE_VARIABLE: &str = "PNG_RS_PASSPHRASE";
+
+/// Environment variable holding the key that picks the pixels used by
+/// `hide` and `reveal`, checked before prompting.
+const STEGO_KEY_VARIABLE: &str = "PNG_RS_STEGO_KEY";
+
+pub fn encode(args: EncodeArgs) -> Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    if chunk_type.is_critical() {
+        return Err(PngError::InvalidArgument {
+            reason: format!("{} is a critical chunk type, message chunks need a lowercase first letter", chunk_type),
+        });
+    }
+    let mut png = read_png(&args.file_path)?;
+
+    let data = seal_message(args.message, args.encrypt, &args.recipients)?;
+    png.insert_chunk(Chunk::new(chunk_type, data), InsertPosition::BeforeEnd)?;
+
+    let output = args.output.as_deref().unwrap_or(&args.file_path);
+    write_png(output, &png)
+}
+
+pub fn decode(args: DecodeArgs) -> Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let png = read_png(&args.file_path)?;
+
+    match png.chunk_by_type(&chunk_type.to_string()) {
+        Some(chunk) if secret::is_encrypted(chunk.data()) => {
+            println!("{}", open_message(chunk.data().to_vec(), &args.identities)?);
+            Ok(())
+        },
+        Some(chunk) => {
+            println!("{}", chunk.data_as_string()?);
+            Ok(())
+        },
+        None => Err(PngError::ChunkNotFound { chunk_type }),
+    }
+}
+
+pub fn hide(args: HideArgs) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    let key = read_secret(STEGO_KEY_VARIABLE, "Stego key", true)?;
+
+    let data = seal_message(args.message, args.encrypt, &args.recipients)?;
+    let hidden = stego::embed(&png, &data, key.as_bytes(), args.bits)?;
+
+    let output = args.output.as_deref().unwrap_or(&args.file_path);
+    write_png(output, &hidden.png)?;
+
+    println!(
+        "Hid {} bytes in {} ({} bytes left with --bits {})",
+        data.len(),
+        output.display(),
+        hidden.capacity - data.len(),
+        args.bits,
+    );
+    Ok(())
+}
+
+pub fn reveal(args: RevealArgs) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    let key = read_secret(STEGO_KEY_VARIABLE, "Stego key", false)?;
+    let data = stego::extract(&png, key.as_bytes(), args.bits)?;
+
+    println!("{}", open_message(data, &args.identities)?);
+    Ok(())
+}
+
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let mut png = read_png(&args.file_path)?;
+
+    let chunk = png.remove_chunk(&chunk_type.to_string())?;
+    write_png(&args.file_path, &png)?;
+
+    println!("Removed {} chunk ({} bytes)", chunk.chunk_type(), chunk.length());
+    Ok(())
+}
+
+pub fn print(args: PrintArgs) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+
+    print!("{}", png);
+    Ok(())
+}
+
+pub fn optimize(args: OptimizeArgs) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+
+    match Optimizer::new().brute_force(args.brute_force).optimize(&png)? {
+        Some(optimized) => {
+            let output = args.output.as_deref().unwrap_or(&args.file_path);
+            write_png(output, &optimized.png)?;
+
+            println!(
+                "{}: {} -> {} bytes ({} {}-bit, {:?} filter, level {})",
+                output.display(),
+                optimized.original_size,
+                optimized.optimized_size,
+                optimized.ihdr.color_type,
+                optimized.ihdr.bit_depth,
+                optimized.filter_strategy,
+                optimized.compression_level,
+            );
+        },
+        None => println!("{}: already optimal, not rewritten", args.file_path.display()),
+    }
+
+    Ok(())
+}
+
+pub fn repair(args: RepairArgs) -> Result<()> {
+    let bytes = read_file(&args.file_path)?;
+    let (png, changes) = repair::repair(&bytes).map_err(|e| e.in_file(&args.file_path))?;
+
+    if changes.is_empty() {
+        println!("{}: nothing to repair, not rewritten", args.file_path.display());
+        return Ok(());
+    }
+
+    let output = args.output.as_deref().unwrap_or(&args.file_path);
+    write_png(output, &png)?;
+
+    for change in &changes {
+        println!("{}", change);
+    }
+    println!("{}: {} changes written", output.display(), changes.len());
+
+    Ok(())
+}
+
+/// Lists every chunk with its offset, length, property bits, CRC status
+/// and decoded contents, then checks chunk ordering and the image data.
+/// Fails if anything is wrong with the file.
+pub fn check(args: CheckArgs) -> Result<ExitCode> {
+    let report = check::check(&read_file(&args.file_path)?);
+
+    for entry in &report.entries {
+        println!("{}", entry);
+    }
+    println!("{}", report.summary(args.file_path.display()));
+
+    Ok(if report.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let (file, public) = match args.sign {
+        true => {
+            let key = SigningKey::generate();
+            (key.to_file(), format!("Verifying key: {}", key.verifying_key()))
+        },
+        false => {
+            let identity = Identity::generate();
+            (identity.to_file(), format!("Public key: {}", identity.to_recipient()))
+        },
+    };
+
+    match args.output {
+        Some(path) => {
+            write_secret_file(&path, file.as_bytes())?;
+            println!("{}", public);
+        },
+        None => print!("{}", *file),
+    }
+
+    Ok(())
+}
+
+pub fn sign(args: SignArgs) -> Result<()> {
+    let key = SigningKey::parse_file(&read_text_file(&args.key)?).map_err(|e| e.in_file(&args.key))?;
+    let chunk_types = args
+        .chunk_types
+        .iter()
+        .map(|chunk_type| ChunkType::from_str(chunk_type))
+        .collect::<Result<Vec<_>>>()?;
+    let mut png = read_png(&args.file_path)?;
+
+    signature::sign(&mut png, &key, &chunk_types)?;
+
+    let output = args.output.as_deref().unwrap_or(&args.file_path);
+    write_png(output, &png)?;
+
+    println!("Signed {} with key {}", output.display(), key.verifying_key().key_id());
+    Ok(())
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let keys = args
+        .keys
+        .iter()
+        .map(|key| VerifyingKey::from_str(key))
+        .collect::<Result<Vec<_>>>()?;
+    let png = read_png(&args.file_path)?;
+
+    let key = signature::verify(&png, &keys)?;
+
+    println!("OK: {} signed by key {} ({})", args.file_path.display(), key.key_id(), key);
+    Ok(())
+}
+
+/// The bytes to store for `message`: encrypted to `recipients` if there are
+/// any, with a passphrase if `encrypt` is set, or as they are.
+fn seal_message(message: String, encrypt: bool, recipients: &[String]) -> Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| Recipient::from_str(recipient))
+        .collect::<Result<Vec<_>>>()?;
+
+    if !recipients.is_empty() {
+        secret::encrypt_to(message.as_bytes(), &recipients)
+    } else if encrypt {
+        secret::encrypt(message.as_bytes(), read_passphrase(true)?.as_bytes())
+    } else {
+        Ok(message.into_bytes())
+    }
+}
+
+/// The message stored as `data`, decrypted first if `seal_message`
+/// encrypted it.
+fn open_message(data: Vec<u8>, identities: &[PathBuf]) -> Result<String> {
+    let message = match secret::is_encrypted(&data) {
+        true => match secret::scheme(&data)? {
+            Scheme::Passphrase => secret::decrypt(&data, read_passphrase(false)?.as_bytes())?,
+            Scheme::Recipients => secret::decrypt_with(&data, &read_identities(identities)?)?,
+        },
+        false => data,
+    };
+
+    String::from_utf8(message).map_err(|_| PngError::InvalidArgument { reason: String::from("message is not valid UTF-8") })
+}
+
+/// Reads every identity in `paths`.
+fn read_identities(paths: &[PathBuf]) -> Result<Vec<Identity>> {
+    if paths.is_empty() {
+        return Err(PngError::InvalidArgument {
+            reason: String::from("message is encrypted to recipients, pass --identity"),
+        });
+    }
+
+    let mut identities = Vec::new();
+    for path in paths {
+        let contents = read_text_file(path)?;
+        identities.extend(Identity::parse_file(&contents).map_err(|e| e.in_file(path))?);
+    }
+
+    Ok(identities)
+}
+
+/// Creates `path` readable only by its owner, refusing to overwrite it.
+fn write_secret_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|e| PngError::from(e).in_file(path))
+}
+
+fn read_passphrase(confirm: bool) -> Result<Zeroizing<String>> {
+    read_secret(PASSPHRASE_VARIABLE, "Passphrase", confirm)
+}
+
+/// Takes a secret from the environment `variable`, or prompts for it on
+/// the terminal as `name`, twice when `confirm` is set. Never taken from
+/// the command line, where other users could see it.
+fn read_secret(variable: &str, name: &str, confirm: bool) -> Result<Zeroizing<String>> {
+    let secret = match std::env::var(variable) {
+        Ok(secret) => Zeroizing::new(secret),
+        Err(_) => {
+            let secret = Zeroizing::new(rpassword::prompt_password(format!("{}: ", name))?);
+            let lowercase = name.to_lowercase();
+            if confirm && *secret != *Zeroizing::new(rpassword::prompt_password(format!("Confirm {}: ", lowercase))?) {
+                return Err(PngError::InvalidArgument { reason: format!("{}s do not match", lowercase) });
+            }
+            secret
+        },
+    };
+
+    if secret.is_empty() {
+        return Err(PngError::InvalidArgument { reason: format!("{} is empty", name.to_lowercase()) });
+    }
+
+    Ok(secret)
+}
+
+fn read_png(path: &Path) -> Result<Png> {
+    let bytes = read_file(path)?;
+
+    Png::try_from(bytes.as_ref()).map_err(|e| e.in_file(path))
+}
+
+fn write_png(path: &Path, png: &Png) -> Result<()> {
+    fs::write(path, png.as_bytes()).map_err(|e| PngError::from(e).in_file(path))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| PngError::from(e).in_file(path))
+}
+
+fn read_text_file(path: &Path) -> Result<Zeroizing<String>> {
+    fs::read_to_string(path).map(Zeroizing::new).map_err(|e| PngError::from(e).in_file(path))
+}
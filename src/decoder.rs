@@ -0,0 +1,344 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::{PngError, Result};
+use crate::adam7::PASSES;
+use crate::chunk_type::ChunkType;
+use crate::filter::{unfilter, FilterType};
+use crate::ihdr::{Ihdr, InterlaceMethod};
+use crate::image::Image;
+use crate::png::Png;
+
+/// Decodes the pixels of `png`: concatenates its `IDAT` chunks, inflates the
+/// zlib stream and reverses the filter applied to each scanline. Interlaced
+/// images are returned de-interlaced.
+pub fn decode(png: &Png) -> Result<Image> {
+    decode_progressive(png, |_, _| {})
+}
+
+/// Like `decode`, but for Adam7 interlaced images calls `on_pass` after each
+/// pass with its number (1 to 7) and the reduced image it holds, so a
+/// preview can be drawn before the whole image is available. Passes that
+/// are empty for small images are skipped.
+pub fn decode_progressive<F>(png: &Png, mut on_pass: F) -> Result<Image>
+where
+    F: FnMut(u8, &Image),
+{
+    let ihdr = png.ihdr()?;
+    let compressed = idat_data(png)?;
+
+    let data = match ihdr.interlace_method {
+        InterlaceMethod::None => {
+            let raw = inflate(&compressed, filtered_size(&ihdr, ihdr.width, ihdr.height)?)?;
+            unfilter_rows(&ihdr, ihdr.width, ihdr.height, &raw)?
+        },
+        InterlaceMethod::Adam7 => {
+            let raw = inflate(&compressed, interlaced_size(&ihdr)?)?;
+            deinterlace(&ihdr, &raw, &mut on_pass)?
+        },
+    };
+
+    Image::new(ihdr, data)
+}
+
+fn idat_data(png: &Png) -> Result<Vec<u8>> {
+    let mut idat_chunks = png
+        .chunks()
+        .iter()
+        .filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+        .peekable();
+
+    if idat_chunks.peek().is_none() {
+        return Err(PngError::ChunkNotFound { chunk_type: ChunkType::IDAT });
+    }
+
+    Ok(idat_chunks.flat_map(|chunk| chunk.data().iter().copied()).collect())
+}
+
+/// Size of `height` filtered rows of `width` pixels, including the filter
+/// type byte at the start of each row.
+fn filtered_size(ihdr: &Ihdr, width: u32, height: u32) -> Result<usize> {
+    (ihdr.row_bytes(width) + 1)
+        .checked_mul(height as usize)
+        .ok_or_else(|| PngError::Unsupported {
+            reason: format!("image of {}x{} pixels is too large", width, height),
+        })
+}
+
+/// Size of all seven filtered Adam7 passes.
+fn interlaced_size(ihdr: &Ihdr) -> Result<usize> {
+    PASSES.iter().try_fold(0usize, |total, pass| {
+        let (width, height) = pass.size(ihdr.width, ihdr.height);
+        let size = match (width, height) {
+            (0, _) | (_, 0) => 0,
+            _ => filtered_size(ihdr, width, height)?,
+        };
+
+        total.checked_add(size).ok_or_else(|| PngError::Unsupported {
+            reason: format!("image of {}x{} pixels is too large", ihdr.width, ihdr.height),
+        })
+    })
+}
+
+/// Inflates at most `expected` bytes. Anything the stream holds beyond that
+/// is ignored, as the spec allows, so a hostile stream cannot make us
+/// allocate more than the header promises.
+fn inflate(compressed: &[u8], expected: usize) -> Result<Vec<u8>> {
+    let mut raw = Vec::new();
+
+    ZlibDecoder::new(compressed)
+        .take(expected as u64)
+        .read_to_end(&mut raw)
+        .map_err(PngError::Zlib)?;
+
+    if raw.len() < expected {
+        return Err(PngError::InvalidImageData {
+            reason: format!("expected {} bytes of decompressed data, got {}", expected, raw.len()),
+        });
+    }
+
+    Ok(raw)
+}
+
+/// Unfilters `height` rows of `width` pixels from the start of `raw`.
+fn unfilter_rows(ihdr: &Ihdr, width: u32, height: u32, raw: &[u8]) -> Result<Vec<u8>> {
+    let row_bytes = ihdr.row_bytes(width);
+    let bpp = ihdr.bits_per_pixel().div_ceil(8);
+    let first_previous = vec![0; row_bytes];
+    let mut data = vec![0; row_bytes * height as usize];
+
+    for (y, line) in raw.chunks_exact(row_bytes + 1).take(height as usize).enumerate() {
+        let filter_type = FilterType::try_from(line[0])
+            .map_err(|_| PngError::InvalidFilterType { filter_type: line[0], row: Some(y as u32) })?;
+
+        let (done, rest) = data.split_at_mut(y * row_bytes);
+        let previous = match y {
+            0 => &first_previous[..],
+            _ => &done[done.len() - row_bytes..],
+        };
+        let current = &mut rest[..row_bytes];
+
+        current.copy_from_slice(&line[1..]);
+        unfilter(filter_type, bpp, previous, current);
+    }
+
+    Ok(data)
+}
+
+/// Unfilters each Adam7 pass in turn and scatters its pixels into the full
+/// image.
+fn deinterlace<F>(ihdr: &Ihdr, raw: &[u8], on_pass: &mut F) -> Result<Vec<u8>>
+where
+    F: FnMut(u8, &Image),
+{
+    let mut data = vec![0; ihdr.row_bytes(ihdr.width) * ihdr.height as usize];
+    let mut offset = 0;
+
+    for (index, pass) in PASSES.iter().enumerate() {
+        let (width, height) = pass.size(ihdr.width, ihdr.height);
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        let size = filtered_size(ihdr, width, height)?;
+        let pass_data = unfilter_rows(ihdr, width, height, &raw[offset..offset + size])?;
+        offset += size;
+
+        pass.scatter(ihdr, &pass_data, &mut data);
+
+        let pass_ihdr = Ihdr { width, height, ..*ihdr };
+        on_pass(index as u8 + 1, &Image::new(pass_ihdr, pass_data)?);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use crate::chunk::Chunk;
+    use crate::ihdr::ColorType;
+
+    /// Builds the unfiltered Adam7 stream for an image whose pixel at
+    /// `(x, y)` is `pixel(x, y)`, using filter type None for every row.
+    fn interlaced_stream(width: u32, height: u32, bits: u8, pixel: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let mut stream = Vec::new();
+
+        for pass in PASSES.iter() {
+            let (pass_width, pass_height) = pass.size(width, height);
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+
+            for py in 0..pass_height {
+                let y = pass.y_start + py * pass.y_step;
+                let mut row = vec![0; (pass_width as usize * bits as usize).div_ceil(8)];
+
+                for px in 0..pass_width {
+                    let x = pass.x_start + px * pass.x_step;
+                    let bit = px as usize * bits as usize;
+                    row[bit / 8] |= pixel(x, y) << (8 - bits as usize - bit % 8);
+                }
+
+                stream.push(0);
+                stream.extend(row);
+            }
+        }
+
+        stream
+    }
+
+    fn testing_png(ihdr: &Ihdr, filtered: &[u8], idat_count: usize) -> Png {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(filtered).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut chunks = vec![ihdr.to_chunk()];
+        let idat_size = compressed.len().div_ceil(idat_count);
+        for part in compressed.chunks(idat_size) {
+            chunks.push(Chunk::new(ChunkType::IDAT, part.to_vec()));
+        }
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_decode_rgb() {
+        let ihdr = Ihdr::new(2, 3, 8, ColorType::Rgb).unwrap();
+        #[rustfmt::skip]
+        let filtered = [
+            1, 10, 20, 30, 5, 5, 5,    // Sub
+            2, 1, 1, 1, 1, 1, 1,       // Up
+            4, 0, 0, 0, 0, 0, 0,       // Paeth
+        ];
+        let png = testing_png(&ihdr, &filtered, 1);
+
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.width(), 2);
+        assert_eq!(image.height(), 3);
+        assert_eq!(image.row(0), &[10, 20, 30, 15, 25, 35]);
+        assert_eq!(image.row(1), &[11, 21, 31, 16, 26, 36]);
+        assert_eq!(image.row(2), &[11, 21, 31, 16, 26, 36]);
+    }
+
+    #[test]
+    fn test_decode_split_idat() {
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Grayscale).unwrap();
+        let filtered: Vec<u8> = (0..4).flat_map(|y| [0, y, y + 1, y + 2, y + 3]).collect();
+        let png = testing_png(&ihdr, &filtered, 3);
+
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.row(3), &[3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_decode_sub_byte() {
+        let ihdr = Ihdr::new(10, 2, 1, ColorType::Grayscale).unwrap();
+        let filtered = [0, 0b1010_1010, 0b1100_0000, 2, 0b1111_1111, 0b0000_0000];
+        let png = testing_png(&ihdr, &filtered, 1);
+
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.row_bytes(), 2);
+        assert_eq!(image.row(1), &[0b1010_1001, 0b1100_0000]);
+    }
+
+    #[test]
+    fn test_decode_average_16_bit() {
+        let ihdr = Ihdr::new(2, 1, 16, ColorType::Grayscale).unwrap();
+        let filtered = [3, 0, 10, 1, 0];
+        let png = testing_png(&ihdr, &filtered, 1);
+
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.row(0), &[0, 10, 1, 5]);
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let ihdr = Ihdr {
+            interlace_method: InterlaceMethod::Adam7,
+            ..Ihdr::new(11, 9, 8, ColorType::Grayscale).unwrap()
+        };
+        let pixel = |x: u32, y: u32| (y * 11 + x) as u8;
+        let png = testing_png(&ihdr, &interlaced_stream(11, 9, 8, pixel), 2);
+
+        let mut passes = Vec::new();
+        let image = decode_progressive(&png, |index, pass| passes.push((index, pass.width(), pass.height()))).unwrap();
+
+        let expected: Vec<u8> = (0..9).flat_map(|y| (0..11).map(move |x| pixel(x, y))).collect();
+        assert_eq!(image.data(), expected.as_slice());
+        assert_eq!(passes, vec![(1, 2, 2), (2, 1, 2), (3, 3, 1), (4, 3, 3), (5, 6, 2), (6, 5, 5), (7, 11, 4)]);
+    }
+
+    #[test]
+    fn test_decode_interlaced_sub_byte() {
+        let ihdr = Ihdr {
+            interlace_method: InterlaceMethod::Adam7,
+            ..Ihdr::new(5, 3, 2, ColorType::Grayscale).unwrap()
+        };
+        let pixel = |x: u32, y: u32| ((x + y) % 4) as u8;
+        let png = testing_png(&ihdr, &interlaced_stream(5, 3, 2, pixel), 1);
+
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.row(0), &[0b0001_1011, 0b0000_0000]);
+        assert_eq!(image.row(1), &[0b0110_1100, 0b0100_0000]);
+        assert_eq!(image.row(2), &[0b1011_0001, 0b1000_0000]);
+    }
+
+    #[test]
+    fn test_decode_interlaced_single_pixel() {
+        let ihdr = Ihdr {
+            interlace_method: InterlaceMethod::Adam7,
+            ..Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()
+        };
+        let png = testing_png(&ihdr, &[0, 1, 2, 3], 1);
+
+        assert_eq!(png.decode().unwrap().data(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_decode_invalid_filter_type() {
+        let ihdr = Ihdr::new(1, 2, 8, ColorType::Grayscale).unwrap();
+        let png = testing_png(&ihdr, &[0, 1, 7, 1], 1);
+
+        assert!(matches!(png.decode(), Err(PngError::InvalidFilterType { filter_type: 7, row: Some(1) })));
+    }
+
+    #[test]
+    fn test_decode_truncated_data() {
+        let ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+        let png = testing_png(&ihdr, &[0, 1, 2, 0, 1], 1);
+
+        assert!(matches!(png.decode(), Err(PngError::InvalidImageData { .. })));
+    }
+
+    #[test]
+    fn test_decode_corrupt_zlib() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::IDAT, vec![1, 2, 3, 4]),
+        ]);
+
+        assert!(matches!(png.decode(), Err(PngError::Zlib(_))));
+    }
+
+    #[test]
+    fn test_decode_missing_idat() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        let png = Png::from_chunks(vec![ihdr.to_chunk()]);
+
+        assert!(matches!(png.decode(), Err(PngError::ChunkNotFound { .. })));
+    }
+}
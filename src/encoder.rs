@@ -0,0 +1,474 @@
+use std::io;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::filter::{entropy, filter, sum_abs, FilterStrategy, FilterType};
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::image::Image;
+use crate::plte::Plte;
+use crate::png::Png;
+use crate::trns::Trns;
+use crate::writer::ChunkWriter;
+
+/// Encodes raw pixel buffers as PNG files.
+///
+/// Pixel data is laid out as in `Image`: rows of `Ihdr::row_bytes` bytes
+/// without filter type bytes, packed for depths below eight bits and
+/// big-endian for 16-bit samples.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    ihdr: Ihdr,
+    palette: Option<Plte>,
+    transparency: Option<Trns>,
+    compression_level: u32,
+    filter_strategy: FilterStrategy,
+}
+
+impl Encoder {
+    /// Largest amount of compressed data written to a single `IDAT` chunk.
+    pub const IDAT_SIZE: usize = 8192;
+    pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+    pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+    /// Filtered rows that precede each candidate when brute-force filter
+    /// selection trial-compresses it.
+    const BRUTE_FORCE_CONTEXT_ROWS: usize = 4;
+
+    pub fn new(width: u32, height: u32, color_type: ColorType, bit_depth: u8) -> Result<Self> {
+        Self::from_ihdr(Ihdr::new(width, height, bit_depth, color_type)?)
+    }
+
+    /// Uses the dimensions and pixel format of `ihdr`, which must pass
+    /// `Ihdr::validate`. The output is never interlaced, whatever `ihdr`
+    /// says.
+    pub fn from_ihdr(ihdr: Ihdr) -> Result<Self> {
+        ihdr.validate()?;
+
+        Ok(Self {
+            ihdr: Ihdr { interlace_method: InterlaceMethod::None, ..ihdr },
+            palette: None,
+            transparency: None,
+            compression_level: Self::DEFAULT_COMPRESSION_LEVEL,
+            filter_strategy: FilterStrategy::default(),
+        })
+    }
+
+    /// Sets the palette. Required for indexed images, optional for
+    /// truecolor ones.
+    pub fn palette(mut self, palette: Plte) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Sets the `tRNS` chunk written after the palette.
+    pub fn transparency(mut self, transparency: Trns) -> Self {
+        self.transparency = Some(transparency);
+        self
+    }
+
+    /// Sets the zlib compression level, from 0 (store only) to 9 (smallest).
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    pub fn filter_strategy(mut self, strategy: FilterStrategy) -> Self {
+        self.filter_strategy = strategy;
+        self
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Result<Png> {
+        Ok(Png::from_chunks(self.chunks(data)?))
+    }
+
+    pub fn encode_image(image: &Image) -> Result<Png> {
+        Self::from_ihdr(*image.ihdr())?.encode(image.data())
+    }
+
+    /// Writes the encoded PNG to `writer` and returns it. The compressed
+    /// data is written in `IDAT_SIZE` chunks as the compressor produces it,
+    /// so unlike `encode` it is never held in memory as a whole.
+    pub fn write<W: Write>(&self, data: &[u8], writer: W) -> Result<W> {
+        self.validate(data)?;
+
+        let mut writer = ChunkWriter::new(writer);
+        for chunk in self.header_chunks() {
+            writer.write_chunk(&chunk)?;
+        }
+
+        let idat = IdatWriter { writer, buffer: Vec::with_capacity(Self::IDAT_SIZE) };
+        let mut encoder = ZlibEncoder::new(idat, Compression::new(self.compression_level));
+        encoder.write_all(&self.filter_rows(data)?)?;
+
+        let mut idat = encoder.finish()?;
+        idat.write_chunk()?;
+        idat.writer.finish()
+    }
+
+    fn chunks(&self, data: &[u8]) -> Result<Vec<Chunk>> {
+        self.validate(data)?;
+
+        let mut chunks = self.header_chunks();
+
+        let compressed = self.compress(&self.filter_rows(data)?)?;
+        for part in compressed.chunks(Self::IDAT_SIZE) {
+            chunks.push(Chunk::new(ChunkType::IDAT, part.to_vec()));
+        }
+
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+
+        Ok(chunks)
+    }
+
+    /// `IHDR` and, if set, `PLTE` and `tRNS`.
+    fn header_chunks(&self) -> Vec<Chunk> {
+        let mut chunks = vec![self.ihdr.to_chunk()];
+
+        if let Some(palette) = &self.palette {
+            chunks.push(palette.to_chunk());
+        }
+        if let Some(transparency) = &self.transparency {
+            chunks.push(transparency.to_chunk());
+        }
+
+        chunks
+    }
+
+    fn validate(&self, data: &[u8]) -> Result<()> {
+        let expected = self.ihdr.row_bytes(self.ihdr.width) as u64 * self.ihdr.height as u64;
+
+        if data.len() as u64 != expected {
+            return Err(PngError::InvalidImageData {
+                reason: format!("expected {} bytes of pixel data, got {}", expected, data.len()),
+            });
+        }
+
+        if self.compression_level > Self::MAX_COMPRESSION_LEVEL {
+            return Err(PngError::InvalidArgument {
+                reason: format!("compression level {} is above {}", self.compression_level, Self::MAX_COMPRESSION_LEVEL),
+            });
+        }
+
+        match (&self.palette, self.ihdr.color_type) {
+            (None, ColorType::Indexed) => Err(PngError::InvalidArgument {
+                reason: String::from("indexed images need a palette"),
+            }),
+            (Some(_), ColorType::Grayscale | ColorType::GrayscaleAlpha) => Err(PngError::InvalidArgument {
+                reason: String::from("grayscale images cannot have a palette"),
+            }),
+            (Some(palette), _) => palette.validate(&self.ihdr),
+            _ => Ok(()),
+        }?;
+
+        match &self.transparency {
+            Some(transparency) => transparency.validate(&self.ihdr, self.palette.as_ref()),
+            None => Ok(()),
+        }
+    }
+
+    /// Prefixes every row with its filter type byte and filters it, choosing
+    /// the filter type according to the filter strategy.
+    fn filter_rows(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let row_bytes = self.ihdr.row_bytes(self.ihdr.width);
+        let bpp = self.ihdr.bits_per_pixel().div_ceil(8);
+        let first_previous = vec![0; row_bytes];
+        let mut candidates = vec![vec![0; row_bytes]; FilterType::ALL.len()];
+        let mut filtered = Vec::with_capacity((row_bytes + 1) * self.ihdr.height as usize);
+
+        for y in 0..self.ihdr.height as usize {
+            let current = &data[y * row_bytes..(y + 1) * row_bytes];
+            let previous = match y {
+                0 => &first_previous[..],
+                _ => &data[(y - 1) * row_bytes..y * row_bytes],
+            };
+
+            let best = match self.filter_strategy {
+                FilterStrategy::Fixed(filter_type) => {
+                    let index = filter_type.value() as usize;
+                    filter(filter_type, bpp, previous, current, &mut candidates[index]);
+                    index
+                },
+                strategy => {
+                    for (filter_type, candidate) in FilterType::ALL.iter().zip(candidates.iter_mut()) {
+                        filter(*filter_type, bpp, previous, current, candidate);
+                    }
+
+                    let mut best = 0;
+                    let mut best_score = f64::INFINITY;
+                    for (index, candidate) in candidates.iter().enumerate() {
+                        let score = self.score(strategy, &filtered, index as u8, candidate)?;
+                        if score < best_score {
+                            best = index;
+                            best_score = score;
+                        }
+                    }
+
+                    best
+                },
+            };
+
+            filtered.push(best as u8);
+            filtered.extend_from_slice(&candidates[best]);
+        }
+
+        Ok(filtered)
+    }
+
+    /// Cost of appending `candidate`, filtered with type `filter_type`, to the
+    /// rows already in `filtered`. Lower is better.
+    fn score(&self, strategy: FilterStrategy, filtered: &[u8], filter_type: u8, candidate: &[u8]) -> Result<f64> {
+        let score = match strategy {
+            FilterStrategy::Fixed(_) | FilterStrategy::MinSum => sum_abs(candidate) as f64,
+            FilterStrategy::Entropy => entropy(candidate),
+            FilterStrategy::BruteForce => {
+                let row_length = candidate.len() + 1;
+                let context = &filtered[filtered.len().saturating_sub(Self::BRUTE_FORCE_CONTEXT_ROWS * row_length)..];
+
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(self.compression_level));
+                encoder.write_all(context).map_err(PngError::Zlib)?;
+                encoder.write_all(&[filter_type]).map_err(PngError::Zlib)?;
+                encoder.write_all(candidate).map_err(PngError::Zlib)?;
+
+                encoder.finish().map_err(PngError::Zlib)?.len() as f64
+            },
+        };
+
+        Ok(score)
+    }
+
+    fn compress(&self, filtered: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(self.compression_level));
+        encoder.write_all(filtered).map_err(PngError::Zlib)?;
+
+        encoder.finish().map_err(PngError::Zlib)
+    }
+}
+
+/// Cuts the zlib stream into `IDAT` chunks of `Encoder::IDAT_SIZE` bytes
+/// and writes each one as soon as it is full.
+struct IdatWriter<W: Write> {
+    writer: ChunkWriter<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> IdatWriter<W> {
+    /// Writes the buffered data, if any, as one `IDAT` chunk.
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        self.writer.write_data(&ChunkType::IDAT, &self.buffer).map_err(|e| match e {
+            PngError::Io(e) => e,
+            e => io::Error::other(e),
+        })?;
+        self.buffer.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IdatWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(Encoder::IDAT_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+
+        if self.buffer.len() == Encoder::IDAT_SIZE {
+            self.write_chunk()?;
+        }
+
+        Ok(taken)
+    }
+
+    /// Does nothing, so that flushes by the compressor do not cut short
+    /// `IDAT` chunks. `ChunkWriter::finish` flushes the underlying writer.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let cases = [
+            (ColorType::Grayscale, 1),
+            (ColorType::Grayscale, 16),
+            (ColorType::Rgb, 8),
+            (ColorType::GrayscaleAlpha, 8),
+            (ColorType::Rgba, 16),
+        ];
+
+        for (color_type, bit_depth) in cases {
+            let encoder = Encoder::new(13, 5, color_type, bit_depth).unwrap();
+            let data = gradient(encoder.ihdr().row_bytes(13) * 5);
+
+            let png = encoder.encode(&data).unwrap();
+            let image = Png::try_from(png.as_bytes().as_ref()).unwrap().decode().unwrap();
+
+            assert_eq!(image.color_type(), color_type);
+            assert_eq!(image.bit_depth(), bit_depth);
+            assert_eq!(image.data(), data.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_encode_indexed() {
+        let encoder = Encoder::new(4, 1, ColorType::Indexed, 2)
+            .unwrap()
+            .palette(Plte::new(vec![[0, 0, 0], [255, 0, 0], [0, 255, 0]]).unwrap())
+            .transparency(Trns::Indexed(vec![0]));
+
+        let png = encoder.encode(&[0b0001_1000]).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        assert_eq!(types, vec!["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+        assert_eq!(png.decode().unwrap().data(), &[0b0001_1000]);
+        assert_eq!(png.decode_rgba8().unwrap().data()[..8], [0, 0, 0, 0, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_encode_compression_levels() {
+        let data = vec![42; 64 * 64 * 3];
+        let stored = Encoder::new(64, 64, ColorType::Rgb, 8).unwrap().compression_level(0).encode(&data).unwrap();
+        let best = Encoder::new(64, 64, ColorType::Rgb, 8).unwrap().compression_level(9).encode(&data).unwrap();
+
+        assert!(best.as_bytes().len() < stored.as_bytes().len());
+        assert_eq!(stored.decode().unwrap().data(), best.decode().unwrap().data());
+    }
+
+    #[test]
+    fn test_encode_splits_idat() {
+        let data: Vec<u8> = (0..256 * 256 * 3).map(|i: u32| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        let png = Encoder::new(256, 256, ColorType::Rgb, 8).unwrap().compression_level(0).encode(&data).unwrap();
+
+        let idat_count = png.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT).count();
+
+        assert!(idat_count > 1);
+        assert_eq!(png.decode().unwrap().data(), data.as_slice());
+    }
+
+    fn filter_types(png: &Png) -> Vec<u8> {
+        let ihdr = png.ihdr().unwrap();
+        let compressed: Vec<u8> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data().to_vec())
+            .collect();
+
+        let mut raw = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(compressed.as_slice()), &mut raw).unwrap();
+
+        raw.chunks(ihdr.row_bytes(ihdr.width) + 1).map(|row| row[0]).collect()
+    }
+
+    #[test]
+    fn test_encode_filter_strategies() {
+        let data: Vec<u8> = (0..32u32).flat_map(|y| (0..32u32).map(move |x| ((x * 3 + y * 5) % 256) as u8)).collect();
+        let strategies = [
+            FilterStrategy::Fixed(FilterType::Paeth),
+            FilterStrategy::MinSum,
+            FilterStrategy::Entropy,
+            FilterStrategy::BruteForce,
+        ];
+
+        for strategy in strategies {
+            let png = Encoder::new(32, 32, ColorType::Grayscale, 8)
+                .unwrap()
+                .filter_strategy(strategy)
+                .encode(&data)
+                .unwrap();
+
+            assert_eq!(png.decode().unwrap().data(), data.as_slice(), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_encode_fixed_filter() {
+        let png = Encoder::new(4, 3, ColorType::Rgb, 8)
+            .unwrap()
+            .filter_strategy(FilterStrategy::Fixed(FilterType::Average))
+            .encode(&gradient(36))
+            .unwrap();
+
+        assert_eq!(filter_types(&png), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn test_encode_min_sum_picks_sub_for_horizontal_gradient() {
+        let data: Vec<u8> = (0..4).flat_map(|_| 0..64).collect();
+        let png = Encoder::new(64, 4, ColorType::Grayscale, 8).unwrap().encode(&data).unwrap();
+
+        assert_eq!(filter_types(&png), vec![1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_encode_adaptive_is_smaller() {
+        let data: Vec<u8> = (0..128u32)
+            .flat_map(|y| (0..128u32).flat_map(move |x| [x as u8, y as u8, (x + y) as u8]))
+            .collect();
+        let encode = |strategy| {
+            Encoder::new(128, 128, ColorType::Rgb, 8)
+                .unwrap()
+                .filter_strategy(strategy)
+                .encode(&data)
+                .unwrap()
+                .as_bytes()
+                .len()
+        };
+
+        assert!(encode(FilterStrategy::MinSum) < encode(FilterStrategy::Fixed(FilterType::None)));
+    }
+
+    #[test]
+    fn test_encode_write() {
+        let encoder = Encoder::new(2, 2, ColorType::Grayscale, 8).unwrap();
+        let bytes = encoder.write(&[1, 2, 3, 4], Vec::new()).unwrap();
+
+        assert_eq!(bytes, encoder.encode(&[1, 2, 3, 4]).unwrap().as_bytes());
+
+        let data: Vec<u8> = (0..256 * 256 * 3).map(|i: u32| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        let encoder = Encoder::new(256, 256, ColorType::Rgb, 8).unwrap().compression_level(0);
+        let bytes = encoder.write(&data, Vec::new()).unwrap();
+
+        assert_eq!(bytes, encoder.encode(&data).unwrap().as_bytes());
+    }
+
+    #[test]
+    fn test_encode_invalid_arguments() {
+        let encoder = Encoder::new(2, 2, ColorType::Grayscale, 8).unwrap();
+
+        assert!(matches!(encoder.encode(&[0; 3]), Err(PngError::InvalidImageData { .. })));
+        assert!(matches!(encoder.clone().compression_level(10).encode(&[0; 4]), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(encoder.clone().palette(Plte::new(vec![[0; 3]]).unwrap()).encode(&[0; 4]), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(encoder.transparency(Trns::Rgb([0; 3])).encode(&[0; 4]), Err(PngError::InvalidChunkData { .. })));
+
+        let indexed = Encoder::new(2, 2, ColorType::Indexed, 1).unwrap();
+        assert!(matches!(indexed.clone().encode(&[0; 2]), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(indexed.palette(Plte::new(vec![[0; 3]; 3]).unwrap()).encode(&[0; 2]), Err(PngError::InvalidChunkData { .. })));
+    }
+
+    #[test]
+    fn test_from_ihdr_validates() {
+        let ihdr = Ihdr::new(2, 2, 8, ColorType::Rgb).unwrap();
+
+        assert!(matches!(Encoder::from_ihdr(Ihdr { bit_depth: 3, ..ihdr }), Err(PngError::InvalidChunkData { .. })));
+        assert!(matches!(Encoder::from_ihdr(Ihdr { width: 0, ..ihdr }), Err(PngError::InvalidChunkData { .. })));
+        assert!(matches!(Encoder::from_ihdr(Ihdr { filter_method: 1, ..ihdr }), Err(PngError::InvalidChunkData { .. })));
+    }
+}
@@ -0,0 +1,160 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+/// Everything that can go wrong while reading, writing or editing a PNG.
+///
+/// Offsets are byte positions counted from the start of the input, or from
+/// the start of the slice handed to the parser when it has no wider context.
+#[derive(Debug, Error)]
+pub enum PngError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid PNG signature: {found:?}")]
+    InvalidSignature { found: Vec<u8> },
+
+    #[error("truncated input{}: expected {expected} bytes, got {actual}", at(.offset))]
+    Truncated { expected: u64, actual: u64, offset: Option<u64> },
+
+    #[error("invalid chunk type {bytes:?}{}", at(.offset))]
+    InvalidChunkType { bytes: Vec<u8>, offset: Option<u64> },
+
+    #[error("chunk length {length}{} exceeds the maximum of {max}", at(.offset), max = crate::chunk::Chunk::MAX_LENGTH)]
+    InvalidLength { length: u64, offset: Option<u64> },
+
+    #[error("{count} trailing bytes{}", at(.offset))]
+    TrailingBytes { count: u64, offset: Option<u64> },
+
+    /// `expected` is the CRC computed over the chunk, `actual` the one stored
+    /// in the input.
+    #[error("CRC mismatch in {chunk_type} chunk{}: expected {expected:#010x}, got {actual:#010x}", at(.offset))]
+    CrcMismatch { chunk_type: ChunkType, expected: u32, actual: u32, offset: Option<u64> },
+
+    #[error("expected {expected} chunk, got {found}")]
+    UnexpectedChunkType { expected: ChunkType, found: ChunkType },
+
+    #[error("invalid {chunk_type} chunk: {reason}")]
+    InvalidChunkData { chunk_type: ChunkType, reason: String },
+
+    #[error("invalid filter type {filter_type}{}", row.map(|row| format!(" in row {}", row)).unwrap_or_default())]
+    InvalidFilterType { filter_type: u8, row: Option<u32> },
+
+    #[error("zlib error: {0}")]
+    Zlib(io::Error),
+
+    #[error("invalid image data: {reason}")]
+    InvalidImageData { reason: String },
+
+    #[error("invalid argument: {reason}")]
+    InvalidArgument { reason: String },
+
+    #[error("unsupported: {reason}")]
+    Unsupported { reason: String },
+
+    #[error("cannot insert {chunk_type} chunk: {reason}")]
+    InvalidChunkOrder { chunk_type: ChunkType, reason: String },
+
+    #[error("cannot decrypt message: {reason}")]
+    Decryption { reason: String },
+
+    #[error("signature verification failed: {reason}")]
+    Verification { reason: String },
+
+    #[error("no hidden message found: {reason}")]
+    MessageNotFound { reason: String },
+
+    #[error("chunk not found: {chunk_type}")]
+    ChunkNotFound { chunk_type: ChunkType },
+
+    #[error("cannot write {chunk_type} chunk after IEND")]
+    ChunkAfterEnd { chunk_type: ChunkType },
+
+    /// Any of the other errors, raised while reading or writing `path`.
+    #[error("{}: {source}", path.display())]
+    File { path: PathBuf, source: Box<PngError> },
+}
+
+impl PngError {
+    /// Attaches the file the error happened in, unless one is already set.
+    pub fn in_file(self, path: &Path) -> Self {
+        match self {
+            PngError::File { .. } => self,
+            _ => PngError::File { path: path.to_path_buf(), source: Box::new(self) },
+        }
+    }
+
+    /// The error itself, without the file it happened in.
+    pub fn kind(&self) -> &PngError {
+        match self {
+            PngError::File { source, .. } => source.kind(),
+            _ => self,
+        }
+    }
+
+    /// Byte offset of the failure, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            PngError::File { source, .. } => source.offset(),
+            PngError::InvalidSignature { .. } => Some(0),
+            PngError::Truncated { offset, .. }
+            | PngError::InvalidChunkType { offset, .. }
+            | PngError::InvalidLength { offset, .. }
+            | PngError::TrailingBytes { offset, .. }
+            | PngError::CrcMismatch { offset, .. } => *offset,
+            _ => None,
+        }
+    }
+
+    /// Shifts a known offset by `base`, for errors raised by a parser that
+    /// only saw part of the input.
+    pub fn offset_by(mut self, base: u64) -> Self {
+        if let PngError::File { path, source } = self {
+            return PngError::File { path, source: Box::new(source.offset_by(base)) };
+        }
+
+        match &mut self {
+            PngError::Truncated { offset, .. }
+            | PngError::InvalidChunkType { offset, .. }
+            | PngError::InvalidLength { offset, .. }
+            | PngError::TrailingBytes { offset, .. }
+            | PngError::CrcMismatch { offset, .. } => {
+                if let Some(offset) = offset {
+                    *offset += base;
+                }
+            },
+            _ => {},
+        }
+
+        self
+    }
+}
+
+fn at(offset: &Option<u64>) -> String {
+    match offset {
+        Some(offset) => format!(" at offset {}", offset),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_file() {
+        let error = PngError::Truncated { expected: 12, actual: 4, offset: Some(8) }.in_file(Path::new("cat.png"));
+
+        assert_eq!(error.to_string(), "cat.png: truncated input at offset 8: expected 12 bytes, got 4");
+        assert!(matches!(error.kind(), PngError::Truncated { .. }));
+        assert_eq!(error.offset_by(2).offset(), Some(10));
+
+        let twice = PngError::ChunkNotFound { chunk_type: ChunkType::IDAT }
+            .in_file(Path::new("a.png"))
+            .in_file(Path::new("b.png"));
+        assert_eq!(twice.to_string(), "a.png: chunk not found: IDAT");
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::{PngError, Result};
+
+/// Per-scanline filter types of filter method 0 (PNG spec, 9.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(PngError::InvalidFilterType { filter_type: value, row: None }),
+        }
+    }
+}
+
+impl Display for FilterType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FilterType::None => "None",
+            FilterType::Sub => "Sub",
+            FilterType::Up => "Up",
+            FilterType::Average => "Average",
+            FilterType::Paeth => "Paeth",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    pub fn value(&self) -> u8 {
+        match self {
+            FilterType::None => 0,
+            FilterType::Sub => 1,
+            FilterType::Up => 2,
+            FilterType::Average => 3,
+            FilterType::Paeth => 4,
+        }
+    }
+}
+
+/// How the encoder picks a filter type for each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+    /// Use the same filter type for every row.
+    Fixed(FilterType),
+    /// Pick the filter whose output has the smallest sum of absolute values
+    /// when read as signed bytes, the heuristic the PNG spec recommends.
+    #[default]
+    MinSum,
+    /// Pick the filter whose output has the lowest Shannon entropy.
+    Entropy,
+    /// Compress every candidate after the rows before it and keep the
+    /// smallest. Much slower, usually a little smaller.
+    BruteForce,
+}
+
+/// Sum of the absolute values of `row` read as signed bytes.
+pub(crate) fn sum_abs(row: &[u8]) -> u64 {
+    row.iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum()
+}
+
+/// Shannon entropy of the byte distribution of `row`, in bits per byte.
+pub(crate) fn entropy(row: &[u8]) -> f64 {
+    let mut counts = [0u32; 256];
+    for &byte in row {
+        counts[byte as usize] += 1;
+    }
+
+    let length = row.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Applies `filter_type` to `current`, writing the filtered bytes to
+/// `output`. Arguments are as for `unfilter`, and `output` must have the same
+/// length as `current`.
+pub fn filter(filter_type: FilterType, bpp: usize, previous: &[u8], current: &[u8], output: &mut [u8]) {
+    for i in 0..current.len() {
+        let left = if i >= bpp { current[i - bpp] } else { 0 };
+        let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let above = previous[i];
+
+        let prediction = match filter_type {
+            FilterType::None => 0,
+            FilterType::Sub => left,
+            FilterType::Up => above,
+            FilterType::Average => ((left as u16 + above as u16) / 2) as u8,
+            FilterType::Paeth => paeth(left, above, upper_left),
+        };
+
+        output[i] = current[i].wrapping_sub(prediction);
+    }
+}
+
+/// Reverses `filter_type` on `current` in place.
+///
+/// `bpp` is the number of bytes per complete pixel, rounded up to one, and
+/// `previous` is the already unfiltered row above, or all zeros for the
+/// first row. Both rows must have the same length.
+pub fn unfilter(filter_type: FilterType, bpp: usize, previous: &[u8], current: &mut [u8]) {
+    match filter_type {
+        FilterType::None => {},
+        FilterType::Sub => {
+            for i in bpp..current.len() {
+                current[i] = current[i].wrapping_add(current[i - bpp]);
+            }
+        },
+        FilterType::Up => {
+            for (byte, &above) in current.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(above);
+            }
+        },
+        FilterType::Average => {
+            for i in 0..current.len() {
+                let left = if i >= bpp { current[i - bpp] } else { 0 };
+                let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                current[i] = current[i].wrapping_add(average);
+            }
+        },
+        FilterType::Paeth => {
+            for i in 0..current.len() {
+                let (left, upper_left) = if i >= bpp {
+                    (current[i - bpp], previous[i - bpp])
+                } else {
+                    (0, 0)
+                };
+                current[i] = current[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        },
+    }
+}
+
+/// The Paeth predictor (PNG spec, 9.4).
+pub(crate) fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREVIOUS: [u8; 6] = [10, 20, 30, 40, 50, 60];
+
+    #[test]
+    fn test_filter_type_from_u8() {
+        assert_eq!(FilterType::try_from(4).unwrap(), FilterType::Paeth);
+        assert!(matches!(FilterType::try_from(5), Err(PngError::InvalidFilterType { filter_type: 5, .. })));
+    }
+
+    #[test]
+    fn test_unfilter_none() {
+        let mut row = [1, 2, 3, 4, 5, 6];
+        unfilter(FilterType::None, 2, &PREVIOUS, &mut row);
+
+        assert_eq!(row, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_unfilter_sub() {
+        let mut row = [1, 2, 3, 4, 255, 6];
+        unfilter(FilterType::Sub, 2, &PREVIOUS, &mut row);
+
+        assert_eq!(row, [1, 2, 4, 6, 3, 12]);
+    }
+
+    #[test]
+    fn test_unfilter_up() {
+        let mut row = [1, 2, 3, 4, 5, 250];
+        unfilter(FilterType::Up, 2, &PREVIOUS, &mut row);
+
+        assert_eq!(row, [11, 22, 33, 44, 55, 54]);
+    }
+
+    #[test]
+    fn test_unfilter_average() {
+        let mut row = [1, 2, 3, 4, 5, 6];
+        unfilter(FilterType::Average, 2, &PREVIOUS, &mut row);
+
+        // left + above halves: 5, 10, (6+30)/2, (12+40)/2, (21+50)/2, (30+60)/2
+        assert_eq!(row, [6, 12, 21, 30, 40, 51]);
+    }
+
+    #[test]
+    fn test_unfilter_paeth() {
+        let mut row = [1, 2, 3, 4, 5, 6];
+        unfilter(FilterType::Paeth, 2, &PREVIOUS, &mut row);
+
+        // Without a left neighbour the predictor is the byte above; after
+        // that it picks whichever of left, above and upper-left is closest
+        // to left + above - upper-left.
+        assert_eq!(row, [11, 22, 33, 44, 55, 66]);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let current = [200, 3, 17, 255, 0, 99];
+
+        for filter_type in FilterType::ALL {
+            let mut row = [0; 6];
+            filter(filter_type, 2, &PREVIOUS, &current, &mut row);
+            unfilter(filter_type, 2, &PREVIOUS, &mut row);
+
+            assert_eq!(row, current, "{} filter", filter_type);
+        }
+    }
+
+    #[test]
+    fn test_sum_abs() {
+        assert_eq!(sum_abs(&[0, 1, 255, 128, 127]), 257);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[7; 16]), 0.0);
+        assert_eq!(entropy(&[0, 1, 2, 3]), 2.0);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+        assert_eq!(paeth(0, 0, 0), 0);
+    }
+}
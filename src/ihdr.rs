@@ -0,0 +1,301 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(invalid(format!("unknown color type {}", value))),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "RGBA",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorType {
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples per pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths the spec allows for this color type (PNG spec, 11.2.2).
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterlaceMethod {
+    None,
+    Adam7,
+}
+
+impl TryFrom<u8> for InterlaceMethod {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(InterlaceMethod::None),
+            1 => Ok(InterlaceMethod::Adam7),
+            _ => Err(invalid(format!("unknown interlace method {}", value))),
+        }
+    }
+}
+
+impl InterlaceMethod {
+    pub fn value(&self) -> u8 {
+        match self {
+            InterlaceMethod::None => 0,
+            InterlaceMethod::Adam7 => 1,
+        }
+    }
+}
+
+/// The image header, which must be the first chunk of every PNG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: InterlaceMethod,
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if *chunk.chunk_type() != ChunkType::IHDR {
+            return Err(PngError::UnexpectedChunkType {
+                expected: ChunkType::IHDR,
+                found: *chunk.chunk_type(),
+            });
+        }
+
+        let data: &[u8; Self::LENGTH] = chunk
+            .data()
+            .try_into()
+            .map_err(|_| invalid(format!("expected {} bytes, got {}", Self::LENGTH, chunk.data().len())))?;
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: InterlaceMethod::try_from(data[12])?,
+        };
+
+        ihdr.validate()?;
+
+        Ok(ihdr)
+    }
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+    pub const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Result<Self> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+
+        ihdr.validate()?;
+
+        Ok(ihdr)
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data: Vec<u8> = self.width
+            .to_be_bytes()
+            .iter()
+            .chain(self.height.to_be_bytes().iter())
+            .chain([
+                self.bit_depth,
+                self.color_type.value(),
+                self.compression_method,
+                self.filter_method,
+                self.interlace_method.value(),
+            ].iter())
+            .copied()
+            .collect();
+
+        Chunk::new(ChunkType::IHDR, data)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.width > Self::MAX_DIMENSION {
+            return Err(invalid(format!("width {} out of range", self.width)));
+        }
+
+        if self.height == 0 || self.height > Self::MAX_DIMENSION {
+            return Err(invalid(format!("height {} out of range", self.height)));
+        }
+
+        if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+            return Err(invalid(format!("bit depth {} not allowed for {} images", self.bit_depth, self.color_type)));
+        }
+
+        if self.compression_method != 0 {
+            return Err(invalid(format!("unknown compression method {}", self.compression_method)));
+        }
+
+        if self.filter_method != 0 {
+            return Err(invalid(format!("unknown filter method {}", self.filter_method)));
+        }
+
+        Ok(())
+    }
+
+    /// Bits used by a single pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in one unfiltered row of `width` pixels, excluding the filter
+    /// type byte.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidChunkData { chunk_type: ChunkType::IHDR, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ihdr_chunk(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Chunk {
+        let data: Vec<u8> = width
+            .to_be_bytes()
+            .iter()
+            .chain(height.to_be_bytes().iter())
+            .chain([bit_depth, color_type, 0, 0, 0].iter())
+            .copied()
+            .collect();
+
+        Chunk::new(ChunkType::IHDR, data)
+    }
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let ihdr = Ihdr::try_from(&ihdr_chunk(640, 480, 8, 6)).unwrap();
+
+        assert_eq!(ihdr.width, 640);
+        assert_eq!(ihdr.height, 480);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace_method, InterlaceMethod::None);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.row_bytes(ihdr.width), 2560);
+    }
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = Ihdr::new(3, 7, 2, ColorType::Grayscale).unwrap();
+        let chunk = ihdr.to_chunk();
+
+        assert_eq!(chunk.as_bytes(), ihdr_chunk(3, 7, 2, 0).as_bytes());
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_ihdr_row_bytes_sub_byte() {
+        let ihdr = Ihdr::new(3, 1, 1, ColorType::Grayscale).unwrap();
+
+        assert_eq!(ihdr.row_bytes(3), 1);
+        assert_eq!(ihdr.row_bytes(9), 2);
+    }
+
+    #[test]
+    fn test_ihdr_invalid_dimensions() {
+        assert!(Ihdr::try_from(&ihdr_chunk(0, 1, 8, 0)).is_err());
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 0, 8, 0)).is_err());
+        assert!(Ihdr::try_from(&ihdr_chunk(1 << 31, 1, 8, 0)).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_invalid_bit_depth() {
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 1, 4, 2)).is_err());
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 1, 16, 3)).is_err());
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 1, 3, 0)).is_err());
+        assert!(Ihdr::try_from(&ihdr_chunk(1, 1, 8, 1)).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_wrong_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0; 13]);
+
+        assert!(matches!(Ihdr::try_from(&chunk), Err(PngError::UnexpectedChunkType { .. })));
+    }
+
+    #[test]
+    fn test_ihdr_wrong_length() {
+        let chunk = Chunk::new(ChunkType::IHDR, vec![0; 12]);
+
+        assert!(matches!(Ihdr::try_from(&chunk), Err(PngError::InvalidChunkData { .. })));
+    }
+}
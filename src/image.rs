@@ -0,0 +1,284 @@
+use crate::{PngError, Result};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::plte::Plte;
+use crate::trns::Trns;
+
+/// A decoded image: unfiltered, de-interlaced scanlines without filter type
+/// bytes, laid out as described by the header.
+///
+/// Samples keep the bit depth of the source, so rows of images with fewer
+/// than eight bits per pixel are packed and 16-bit samples are big-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Builds an image from samples laid out as returned by `samples`. Every
+    /// sample must fit in the bit depth.
+    pub fn from_samples(ihdr: Ihdr, samples: &[u16]) -> Result<Self> {
+        let bit_depth = ihdr.bit_depth as usize;
+        let row_samples = ihdr.width as usize * ihdr.color_type.channels();
+        let row_bytes = ihdr.row_bytes(ihdr.width);
+
+        if samples.len() as u64 != row_samples as u64 * ihdr.height as u64 {
+            return Err(PngError::InvalidImageData {
+                reason: format!("expected {} samples, got {}", row_samples as u64 * ihdr.height as u64, samples.len()),
+            });
+        }
+
+        if let Some(&sample) = samples.iter().find(|&&sample| bit_depth < 16 && sample >> bit_depth != 0) {
+            return Err(PngError::InvalidArgument {
+                reason: format!("sample {} does not fit in {} bits", sample, bit_depth),
+            });
+        }
+
+        let mut data = vec![0; row_bytes * ihdr.height as usize];
+
+        for (row, row_samples) in data.chunks_exact_mut(row_bytes).zip(samples.chunks(row_samples.max(1))) {
+            for (i, &sample) in row_samples.iter().enumerate() {
+                match bit_depth {
+                    16 => row[2 * i..2 * i + 2].copy_from_slice(&sample.to_be_bytes()),
+                    8 => row[i] = sample as u8,
+                    _ => {
+                        let bit = i * bit_depth;
+                        row[bit / 8] |= (sample as u8) << (8 - bit_depth - bit % 8);
+                    },
+                }
+            }
+        }
+
+        Self::new(ihdr, data)
+    }
+
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> Result<Self> {
+        let expected = ihdr.row_bytes(ihdr.width) as u64 * ihdr.height as u64;
+
+        if data.len() as u64 != expected {
+            return Err(PngError::InvalidImageData {
+                reason: format!("expected {} bytes of pixel data, got {}", expected, data.len()),
+            });
+        }
+
+        Ok(Self { ihdr, data })
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn width(&self) -> u32 {
+        self.ihdr.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ihdr.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.ihdr.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.ihdr.color_type
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        self.ihdr.row_bytes(self.ihdr.width)
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let row_bytes = self.row_bytes();
+        let start = y as usize * row_bytes;
+
+        &self.data[start..start + row_bytes]
+    }
+
+    /// Every sample of the image in row-major order, one value per channel
+    /// at the image's own bit depth. Indexed images yield palette indices.
+    pub fn samples(&self) -> Vec<u16> {
+        let bit_depth = self.bit_depth() as usize;
+        let row_samples = self.width() as usize * self.color_type().channels();
+        let mut samples = Vec::with_capacity(row_samples * self.height() as usize);
+
+        for y in 0..self.height() {
+            let row = self.row(y);
+
+            for i in 0..row_samples {
+                let sample = match bit_depth {
+                    16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]),
+                    8 => row[i] as u16,
+                    _ => {
+                        let bit = i * bit_depth;
+                        ((row[bit / 8] >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1)) as u16
+                    },
+                };
+
+                samples.push(sample);
+            }
+        }
+
+        samples
+    }
+
+    /// Expands every pixel to 16-bit RGBA. Indexed images are looked up in
+    /// `plte`, and `trns` supplies palette alpha or the color key that
+    /// marks fully transparent pixels.
+    pub fn to_rgba16(&self, plte: Option<&Plte>, trns: Option<&Trns>) -> Result<Vec<[u16; 4]>> {
+        if let Some(trns) = trns {
+            trns.validate(&self.ihdr, plte)?;
+        }
+
+        let samples = self.samples();
+        let scale = 65535 / ((1u32 << self.bit_depth()) - 1) as u16;
+        let alpha = |key: bool| if key { 0 } else { 65535 };
+
+        let pixels = match self.color_type() {
+            ColorType::Grayscale => samples
+                .iter()
+                .map(|&g| [g * scale, g * scale, g * scale, alpha(trns == Some(&Trns::Gray(g)))])
+                .collect(),
+            ColorType::GrayscaleAlpha => samples
+                .chunks_exact(2)
+                .map(|p| [p[0] * scale, p[0] * scale, p[0] * scale, p[1] * scale])
+                .collect(),
+            ColorType::Rgb => samples
+                .chunks_exact(3)
+                .map(|p| {
+                    let key = trns == Some(&Trns::Rgb([p[0], p[1], p[2]]));
+                    [p[0] * scale, p[1] * scale, p[2] * scale, alpha(key)]
+                })
+                .collect(),
+            ColorType::Rgba => samples
+                .chunks_exact(4)
+                .map(|p| [p[0] * scale, p[1] * scale, p[2] * scale, p[3] * scale])
+                .collect(),
+            ColorType::Indexed => {
+                let plte = plte.ok_or(PngError::ChunkNotFound { chunk_type: ChunkType::PLTE })?;
+                let palette_alpha = match trns {
+                    Some(Trns::Indexed(alpha)) => alpha.as_slice(),
+                    _ => &[],
+                };
+
+                samples
+                    .iter()
+                    .map(|&index| {
+                        let index = index as usize;
+                        let entry = plte.entries().get(index).ok_or_else(|| PngError::InvalidImageData {
+                            reason: format!("palette index {} out of range", index),
+                        })?;
+                        let alpha = palette_alpha.get(index).copied().unwrap_or(255);
+
+                        Ok([entry[0] as u16 * 257, entry[1] as u16 * 257, entry[2] as u16 * 257, alpha as u16 * 257])
+                    })
+                    .collect::<Result<_>>()?
+            },
+        };
+
+        Ok(pixels)
+    }
+
+    /// Converts to an 8-bit RGBA image as described for `to_rgba16`.
+    /// 16-bit samples are rounded to the nearest 8-bit value.
+    pub fn to_rgba8(&self, plte: Option<&Plte>, trns: Option<&Trns>) -> Result<Image> {
+        let ihdr = Ihdr::new(self.width(), self.height(), 8, ColorType::Rgba)?;
+        let data = self
+            .to_rgba16(plte, trns)?
+            .iter()
+            .flat_map(|pixel| pixel.map(|sample| ((sample as u32 + 128) / 257) as u8))
+            .collect();
+
+        Image::new(ihdr, data)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_rows() {
+        let ihdr = Ihdr::new(2, 2, 8, ColorType::Rgb).unwrap();
+        let image = Image::new(ihdr, (0..12).collect()).unwrap();
+
+        assert_eq!(image.row_bytes(), 6);
+        assert_eq!(image.row(1), &[6, 7, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_image_samples() {
+        let ihdr = Ihdr::new(3, 2, 2, ColorType::Grayscale).unwrap();
+        let image = Image::new(ihdr, vec![0b0001_1000, 0b1110_0100]).unwrap();
+
+        assert_eq!(image.samples(), vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(Image::from_samples(ihdr, &image.samples()).unwrap().data(), &[0b0001_1000, 0b1110_0100]);
+        assert!(matches!(Image::from_samples(ihdr, &[0, 1, 4, 3, 2, 1]), Err(PngError::InvalidArgument { .. })));
+
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap();
+        assert!(matches!(Image::from_samples(ihdr, &[256]), Err(PngError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_image_samples_16_bit() {
+        let ihdr = Ihdr::new(1, 1, 16, ColorType::GrayscaleAlpha).unwrap();
+        let image = Image::new(ihdr, vec![1, 2, 255, 254]).unwrap();
+
+        assert_eq!(image.samples(), vec![0x0102, 0xfffe]);
+        assert_eq!(Image::from_samples(ihdr, &image.samples()).unwrap(), image);
+    }
+
+    #[test]
+    fn test_image_indexed_to_rgba8() {
+        let ihdr = Ihdr::new(4, 1, 2, ColorType::Indexed).unwrap();
+        let image = Image::new(ihdr, vec![0b0001_1000]).unwrap();
+        let plte = Plte::new(vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]]).unwrap();
+        let trns = Trns::Indexed(vec![0, 128]);
+
+        let rgba = image.to_rgba8(Some(&plte), Some(&trns)).unwrap();
+
+        assert_eq!(rgba.color_type(), ColorType::Rgba);
+        assert_eq!(rgba.data(), &[255, 0, 0, 0, 0, 255, 0, 128, 0, 0, 255, 255, 255, 0, 0, 0]);
+        assert!(matches!(image.to_rgba8(None, None), Err(PngError::ChunkNotFound { .. })));
+    }
+
+    #[test]
+    fn test_image_indexed_out_of_range() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        let image = Image::new(ihdr, vec![3]).unwrap();
+        let plte = Plte::new(vec![[0; 3]; 3]).unwrap();
+
+        assert!(matches!(image.to_rgba8(Some(&plte), None), Err(PngError::InvalidImageData { .. })));
+    }
+
+    #[test]
+    fn test_image_color_key_to_rgba8() {
+        let gray = Image::new(Ihdr::new(2, 1, 4, ColorType::Grayscale).unwrap(), vec![0x3f]).unwrap();
+        let rgba = gray.to_rgba8(None, Some(&Trns::Gray(3))).unwrap();
+
+        assert_eq!(rgba.data(), &[51, 51, 51, 0, 255, 255, 255, 255]);
+
+        let ihdr = Ihdr::new(2, 1, 16, ColorType::Rgb).unwrap();
+        let rgb = Image::from_samples(ihdr, &[1, 2, 3, 0xffff, 0x8000, 0]).unwrap();
+        let rgba = rgb.to_rgba8(None, Some(&Trns::Rgb([1, 2, 3]))).unwrap();
+
+        assert_eq!(rgba.data(), &[0, 0, 0, 0, 255, 128, 0, 255]);
+    }
+
+    #[test]
+    fn test_image_wrong_size() {
+        let ihdr = Ihdr::new(3, 1, 1, ColorType::Grayscale).unwrap();
+
+        assert!(Image::new(ihdr, vec![0; 1]).is_ok());
+        assert!(Image::new(ihdr, vec![0; 2]).is_err());
+    }
+}
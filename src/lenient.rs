@@ -0,0 +1,277 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::{PngError, Result};
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::ordering::is_known;
+use crate::png::Png;
+
+/// Most chunk data hashed by `find_chunk_start` in one search.
+const MAX_SEARCH_CRC_BYTES: usize = 1 << 24;
+
+/// A problem the lenient parser stepped over. Offsets are those of the
+/// start of the affected chunk, or of the first unreadable byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// `expected` is the CRC computed over the chunk, `actual` the stored
+    /// one, which the recovered chunk keeps.
+    CrcMismatch { chunk_type: ChunkType, expected: u32, actual: u32, offset: u64 },
+    /// A critical chunk this library does not know, which decoders must
+    /// refuse.
+    UnknownCriticalChunk { chunk_type: ChunkType, offset: u64 },
+    ReservedBitSet { chunk_type: ChunkType, offset: u64 },
+    /// Bytes from `offset` that do not form a chunk, skipped up to the next
+    /// chunk that does.
+    SkippedBytes { count: u64, offset: u64, reason: String },
+    /// Bytes from `offset` to the end that do not form a chunk.
+    TrailingBytes { count: u64, offset: u64, reason: String },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::CrcMismatch { chunk_type, expected, actual, offset } => write!(
+                f,
+                "CRC mismatch in {} chunk at offset {}: expected {:#010x}, got {:#010x}",
+                chunk_type, offset, expected, actual,
+            ),
+            Warning::UnknownCriticalChunk { chunk_type, offset } => {
+                write!(f, "unknown critical chunk {} at offset {}", chunk_type, offset)
+            },
+            Warning::ReservedBitSet { chunk_type, offset } => {
+                write!(f, "reserved bit set in {} chunk at offset {}", chunk_type, offset)
+            },
+            Warning::SkippedBytes { count, offset, reason } => {
+                write!(f, "{} unreadable bytes at offset {} skipped ({})", count, offset, reason)
+            },
+            Warning::TrailingBytes { count, offset, reason } => {
+                write!(f, "{} trailing bytes at offset {} ({})", count, offset, reason)
+            },
+        }
+    }
+}
+
+impl Warning {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Warning::CrcMismatch { offset, .. }
+            | Warning::UnknownCriticalChunk { offset, .. }
+            | Warning::ReservedBitSet { offset, .. }
+            | Warning::SkippedBytes { offset, .. }
+            | Warning::TrailingBytes { offset, .. } => *offset,
+        }
+    }
+}
+
+/// Parses a PNG file, keeping every chunk that can be read and recording
+/// what is wrong with it instead of failing. Only a missing signature is an
+/// error. Bytes that do not form a chunk are skipped up to the next chunk
+/// with a known type or a matching CRC; if there is none, reading stops.
+pub fn parse(bytes: &[u8]) -> Result<(Png, Vec<Warning>)> {
+    let header_length = Png::STANDARD_HEADER.len();
+
+    if bytes.len() < header_length {
+        return Err(PngError::Truncated {
+            expected: header_length as u64,
+            actual: bytes.len() as u64,
+            offset: Some(0),
+        });
+    }
+
+    if bytes[..header_length] != Png::STANDARD_HEADER {
+        return Err(PngError::InvalidSignature { found: bytes[..header_length].to_vec() });
+    }
+
+    let mut chunks = Vec::new();
+    let mut warnings = Vec::new();
+    let mut offset = header_length;
+
+    while offset < bytes.len() {
+        let (chunk, used) = match Chunk::parse_unverified(&bytes[offset..]) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let Some(next) = find_chunk_start(bytes, offset + 1) else {
+                    warnings.push(Warning::TrailingBytes {
+                        count: (bytes.len() - offset) as u64,
+                        offset: offset as u64,
+                        reason: e.to_string(),
+                    });
+                    break;
+                };
+
+                warnings.push(Warning::SkippedBytes {
+                    count: (next - offset) as u64,
+                    offset: offset as u64,
+                    reason: e.to_string(),
+                });
+                offset = next;
+                continue;
+            },
+        };
+
+        let chunk_type = *chunk.chunk_type();
+        let chunk_offset = offset as u64;
+
+        if !chunk.crc_matches() {
+            warnings.push(Warning::CrcMismatch {
+                chunk_type,
+                expected: Chunk::calculate_crc(&chunk_type, chunk.data()),
+                actual: chunk.crc(),
+                offset: chunk_offset,
+            });
+        }
+
+        if chunk_type.is_critical() && !is_known(&chunk_type) {
+            warnings.push(Warning::UnknownCriticalChunk { chunk_type, offset: chunk_offset });
+        }
+
+        if !chunk_type.is_reserved_bit_valid() {
+            warnings.push(Warning::ReservedBitSet { chunk_type, offset: chunk_offset });
+        }
+
+        chunks.push(chunk);
+        offset += used;
+    }
+
+    Ok((Png::from_chunks(chunks), warnings))
+}
+
+/// Finds the first offset from `from` on where a chunk starts that is
+/// unlikely to be a chance match: it fits and either has a known type, or
+/// is followed by another chunk header and has a matching CRC. At most
+/// `MAX_SEARCH_CRC_BYTES` are hashed, so crafted input cannot make the
+/// search take quadratic time.
+pub(crate) fn find_chunk_start(bytes: &[u8], from: usize) -> Option<usize> {
+    let mut crc_budget = MAX_SEARCH_CRC_BYTES;
+
+    (from..bytes.len()).find(|&start| {
+        let Ok((chunk, used)) = ChunkRef::parse_unverified(&bytes[start..]) else {
+            return false;
+        };
+        if is_known(chunk.chunk_type()) {
+            return true;
+        }
+        if !is_chunk_header(&bytes[start + used..]) || chunk.data().len() > crc_budget {
+            return false;
+        }
+
+        crc_budget -= chunk.data().len();
+        chunk.crc_matches()
+    })
+}
+
+/// Whether `bytes` starts with a plausible chunk length and type.
+fn is_chunk_header(bytes: &[u8]) -> bool {
+    let Some((length, rest)) = bytes.split_first_chunk::<{ Chunk::LENGTH_BYTES }>() else {
+        return false;
+    };
+
+    u32::from_be_bytes(*length) <= Chunk::MAX_LENGTH
+        && rest
+            .first_chunk::<{ Chunk::CHUNK_TYPE_BYTES }>()
+            .is_some_and(|chunk_type| ChunkType::try_from(*chunk_type).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn file(chunks: &[Chunk]) -> Vec<u8> {
+        Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect()
+    }
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_lenient_clean_file() {
+        let bytes = file(&[chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        let (png, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_lenient_crc_mismatch() {
+        let mut bytes = file(&[chunk("IHDR", &[0; 13]), chunk("ruSt", b"hi"), chunk("IEND", &[])]);
+        bytes[8 + 25 + 10] ^= 0xff;
+
+        let (png, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 3);
+        assert!(!png.chunks()[1].crc_matches());
+        assert!(matches!(warnings[..], [Warning::CrcMismatch { offset: 33, .. }]));
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_lenient_chunk_properties() {
+        let bytes = file(&[chunk("IHDR", &[0; 13]), chunk("RUsT", &[]), chunk("IEND", &[])]);
+        let (_, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::UnknownCriticalChunk { chunk_type: ChunkType::from_str("RUsT").unwrap(), offset: 33 },
+                Warning::ReservedBitSet { chunk_type: ChunkType::from_str("RUsT").unwrap(), offset: 33 },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lenient_trailing_garbage() {
+        let mut bytes = file(&[chunk("IHDR", &[0; 13]), chunk("IEND", &[])]);
+        let end = bytes.len() as u64;
+        bytes.extend_from_slice(b"\x00\x00garbage");
+
+        let (png, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert!(matches!(warnings[..], [Warning::TrailingBytes { count: 9, offset, .. }] if offset == end));
+    }
+
+    #[test]
+    fn test_lenient_resynchronises() {
+        let mut bytes = file(&[chunk("IHDR", &[0; 13])]);
+        let garbage = bytes.len() as u64;
+        bytes.extend_from_slice(b"\xff\xff\xff\xffjunk");
+        bytes.extend(file(&[chunk("ruSt", b"hi"), chunk("IEND", &[])]).split_off(8));
+
+        let (png, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "ruSt");
+        assert!(matches!(warnings[..], [Warning::SkippedBytes { count: 8, offset, .. }] if offset == garbage));
+    }
+
+    #[test]
+    fn test_lenient_resync_is_bounded() {
+        let mut bytes = file(&[chunk("IHDR", &[0; 13])]);
+        let garbage = bytes.len() as u64;
+        bytes.extend_from_slice(b"\xff\xff\xff\xffjunk");
+        // A plausible header with a 256 KiB length every 8 bytes.
+        for _ in 0..(1 << 17) {
+            bytes.extend_from_slice(&(1u32 << 18).to_be_bytes());
+            bytes.extend_from_slice(b"aaaa");
+        }
+
+        let (png, warnings) = parse(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 1);
+        assert!(matches!(warnings[..], [Warning::TrailingBytes { offset, .. }] if offset == garbage));
+    }
+
+    #[test]
+    fn test_lenient_invalid_signature() {
+        assert!(matches!(parse(b"\x89PNG\r\n\x1a\x00"), Err(PngError::InvalidSignature { .. })));
+        assert!(matches!(parse(b"\x89PNG"), Err(PngError::Truncated { .. })));
+    }
+}
@@ -0,0 +1,29 @@
+pub mod adam7;
+pub mod check;
+pub mod checksum;
+pub mod chunk;
+pub mod chunk_type;
+pub mod decoder;
+pub mod encoder;
+pub mod error;
+pub mod filter;
+pub mod ihdr;
+pub mod image;
+pub mod lenient;
+pub mod optimize;
+pub mod ordering;
+pub mod plte;
+pub mod png;
+pub mod reader;
+pub mod recipient;
+pub mod repair;
+pub mod secret;
+pub mod signature;
+pub mod stego;
+pub mod text;
+pub mod trns;
+pub mod writer;
+
+pub use error::PngError;
+
+pub type Result<T> = std::result::Result<T, PngError>;
@@ -1,9 +1,39 @@
-mod chunk;
-mod chunk_type;
+mod args;
+mod commands;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    todo!()
-}
\ No newline at end of file
+use clap::Parser;
+
+use png_rs::Result;
+
+use crate::args::{Cli, Command};
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    let success = |()| ExitCode::SUCCESS;
+
+    match cli.command {
+        Command::Encode(args) => commands::encode(args).map(success),
+        Command::Decode(args) => commands::decode(args).map(success),
+        Command::Hide(args) => commands::hide(args).map(success),
+        Command::Reveal(args) => commands::reveal(args).map(success),
+        Command::Remove(args) => commands::remove(args).map(success),
+        Command::Print(args) => commands::print(args).map(success),
+        Command::Optimize(args) => commands::optimize(args).map(success),
+        Command::Repair(args) => commands::repair(args).map(success),
+        Command::Keygen(args) => commands::keygen(args).map(success),
+        Command::Sign(args) => commands::sign(args).map(success),
+        Command::Verify(args) => commands::verify(args).map(success),
+        Command::Check(args) => commands::check(args),
+    }
+}
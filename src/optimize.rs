@@ -0,0 +1,451 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{PngError, Result};
+use crate::chunk_type::ChunkType;
+use crate::encoder::Encoder;
+use crate::filter::{FilterStrategy, FilterType};
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::image::Image;
+use crate::plte::Plte;
+use crate::png::Png;
+use crate::trns::Trns;
+
+/// Ancillary chunks whose contents depend on the color type, bit depth or
+/// palette. Files with any of them are only recompressed, never converted.
+const COLOR_DEPENDENT: [ChunkType; 4] = [ChunkType::TRNS, ChunkType::BKGD, ChunkType::SBIT, ChunkType::HIST];
+
+const COMPRESSION_LEVELS: [u32; 2] = [9, 6];
+
+/// Losslessly shrinks PNG files.
+///
+/// The image is decoded and re-encoded in every smaller pixel format that
+/// can represent it exactly (lower bit depth, grayscale, no alpha channel,
+/// or a palette of at most 256 colors), with several filter strategies and
+/// compression levels. A result is only returned if it is smaller than the
+/// input and decodes to the same pixels. Ancillary chunks are kept; the
+/// output is never interlaced.
+#[derive(Debug, Clone, Default)]
+pub struct Optimizer {
+    brute_force: bool,
+}
+
+/// The smallest encoding the optimizer found.
+#[derive(Debug)]
+pub struct Optimized {
+    pub png: Png,
+    pub ihdr: Ihdr,
+    pub filter_strategy: FilterStrategy,
+    pub compression_level: u32,
+    pub original_size: usize,
+    pub optimized_size: usize,
+}
+
+/// A pixel format to try, with the image converted to it.
+struct Format {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+    palette: Option<Plte>,
+    transparency: Option<Trns>,
+    replace_palette: bool,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also tries brute-force filter selection, which is much slower.
+    pub fn brute_force(mut self, enabled: bool) -> Self {
+        self.brute_force = enabled;
+        self
+    }
+
+    /// Returns the smallest pixel-identical encoding of `png`, or `None` if
+    /// nothing smaller than the input was found.
+    pub fn optimize(&self, png: &Png) -> Result<Option<Optimized>> {
+        let original_size = png.as_bytes().len();
+        let image = png.decode()?;
+        let palette = png.plte()?;
+        let pixels = image.to_rgba16(palette.as_ref(), png.trns()?.as_ref())?;
+
+        let keep_format = png
+            .chunks()
+            .iter()
+            .any(|chunk| COLOR_DEPENDENT.contains(chunk.chunk_type()));
+
+        let formats = if keep_format {
+            vec![Format {
+                ihdr: Ihdr { interlace_method: InterlaceMethod::None, ..*image.ihdr() },
+                data: image.into_data(),
+                palette,
+                transparency: None,
+                replace_palette: false,
+            }]
+        } else {
+            candidate_formats(&image, &pixels)?
+        };
+
+        let mut best: Option<Optimized> = None;
+
+        for format in &formats {
+            for filter_strategy in self.filter_strategies() {
+                for compression_level in COMPRESSION_LEVELS {
+                    let mut encoder = Encoder::from_ihdr(format.ihdr)?
+                        .filter_strategy(filter_strategy)
+                        .compression_level(compression_level);
+                    if let Some(palette) = &format.palette {
+                        encoder = encoder.palette(palette.clone());
+                    }
+                    if let Some(transparency) = &format.transparency {
+                        encoder = encoder.transparency(transparency.clone());
+                    }
+
+                    let candidate = merge(png, &encoder.encode(&format.data)?, format);
+                    let size = candidate.as_bytes().len();
+
+                    let best_size = best.as_ref().map_or(original_size, |best| best.optimized_size);
+                    if size >= best_size || verify(&candidate, &pixels).is_err() {
+                        continue;
+                    }
+
+                    best = Some(Optimized {
+                        png: candidate,
+                        ihdr: format.ihdr,
+                        filter_strategy,
+                        compression_level,
+                        original_size,
+                        optimized_size: size,
+                    });
+                }
+            }
+        }
+
+        Ok(best)
+    }
+
+    fn filter_strategies(&self) -> Vec<FilterStrategy> {
+        let mut strategies = vec![
+            FilterStrategy::Fixed(FilterType::None),
+            FilterStrategy::MinSum,
+            FilterStrategy::Entropy,
+        ];
+
+        if self.brute_force {
+            strategies.push(FilterStrategy::BruteForce);
+        }
+
+        strategies
+    }
+}
+
+/// Every format smaller than RGBA that represents `pixels` exactly, plus
+/// RGBA itself.
+fn candidate_formats(image: &Image, pixels: &[[u16; 4]]) -> Result<Vec<Format>> {
+    let gray = pixels.iter().all(|p| p[0] == p[1] && p[1] == p[2]);
+    let opaque = pixels.iter().all(|p| p[3] == 65535);
+    let depth_8 = pixels.iter().all(|p| p.iter().all(|&sample| sample % 257 == 0));
+    let color_depth = if depth_8 { 8 } else { 16 };
+
+    let mut formats = Vec::new();
+
+    if gray && opaque {
+        let bit_depth = [1, 2, 4, 8]
+            .into_iter()
+            .find(|&bit_depth| {
+                let scale = 65535 / ((1u32 << bit_depth) - 1) as u16;
+                pixels.iter().all(|p| p[0] % scale == 0)
+            })
+            .unwrap_or(16);
+
+        formats.push(convert(image, pixels, ColorType::Grayscale, bit_depth, &[0])?);
+    }
+
+    if gray {
+        formats.push(convert(image, pixels, ColorType::GrayscaleAlpha, color_depth, &[0, 3])?);
+    }
+
+    if opaque {
+        formats.push(convert(image, pixels, ColorType::Rgb, color_depth, &[0, 1, 2])?);
+    }
+
+    formats.push(convert(image, pixels, ColorType::Rgba, color_depth, &[0, 1, 2, 3])?);
+
+    if depth_8 {
+        if let Some(format) = indexed(image, pixels)? {
+            formats.push(format);
+        }
+    }
+
+    Ok(formats)
+}
+
+/// Converts `pixels` to `color_type` at `bit_depth`, keeping `channels` of
+/// each RGBA pixel.
+fn convert(image: &Image, pixels: &[[u16; 4]], color_type: ColorType, bit_depth: u8, channels: &[usize]) -> Result<Format> {
+    let ihdr = Ihdr::new(image.width(), image.height(), bit_depth, color_type)?;
+    let scale = 65535 / ((1u32 << bit_depth) - 1) as u16;
+
+    let samples: Vec<u16> = pixels
+        .iter()
+        .flat_map(|p| channels.iter().map(move |&channel| p[channel] / scale))
+        .collect();
+
+    Ok(Format {
+        ihdr,
+        data: Image::from_samples(ihdr, &samples)?.into_data(),
+        palette: None,
+        transparency: None,
+        replace_palette: true,
+    })
+}
+
+/// Builds a palette image if `pixels` has at most 256 distinct colors.
+/// Translucent colors come first so the `tRNS` chunk can stay short.
+fn indexed(image: &Image, pixels: &[[u16; 4]]) -> Result<Option<Format>> {
+    let mut colors: Vec<[u8; 4]> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for p in pixels {
+        if seen.insert(rgba8(p)) {
+            if colors.len() == 256 {
+                return Ok(None);
+            }
+            colors.push(rgba8(p));
+        }
+    }
+
+    colors.sort_by_key(|color| color[3] == 255);
+    let indices: HashMap<[u8; 4], u16> = colors
+        .iter()
+        .enumerate()
+        .map(|(index, &color)| (color, index as u16))
+        .collect();
+
+    let bit_depth = match colors.len() {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+    let ihdr = Ihdr::new(image.width(), image.height(), bit_depth, ColorType::Indexed)?;
+
+    let samples: Vec<u16> = pixels
+        .iter()
+        .map(|p| indices[&rgba8(p)])
+        .collect();
+
+    let palette = Plte::new(colors.iter().map(|color| [color[0], color[1], color[2]]).collect())?;
+    let transparency: Vec<u8> = colors
+        .iter()
+        .take_while(|color| color[3] != 255)
+        .map(|color| color[3])
+        .collect();
+
+    Ok(Some(Format {
+        ihdr,
+        data: Image::from_samples(ihdr, &samples)?.into_data(),
+        palette: Some(palette),
+        transparency: (!transparency.is_empty()).then_some(Trns::Indexed(transparency)),
+        replace_palette: true,
+    }))
+}
+
+fn rgba8(pixel: &[u16; 4]) -> [u8; 4] {
+    pixel.map(|sample| (sample / 257) as u8)
+}
+
+/// Replaces the header and image data of `original` with those of
+/// `encoded`, keeping every other chunk where it was. If the format replaces
+/// the palette, the `PLTE` and `tRNS` chunks of `encoded` are used as well.
+fn merge(original: &Png, encoded: &Png, format: &Format) -> Png {
+    let mut chunks = Vec::new();
+    let mut idat_written = false;
+
+    for chunk in original.chunks() {
+        match *chunk.chunk_type() {
+            ChunkType::IHDR => chunks.extend(encoded.chunk_by_type("IHDR").cloned()),
+            ChunkType::PLTE | ChunkType::TRNS if format.replace_palette => {},
+            ChunkType::IDAT if idat_written => {},
+            ChunkType::IDAT => {
+                chunks.extend(
+                    encoded
+                        .chunks()
+                        .iter()
+                        .filter(|chunk| match *chunk.chunk_type() {
+                            ChunkType::PLTE | ChunkType::TRNS => format.replace_palette,
+                            chunk_type => chunk_type == ChunkType::IDAT,
+                        })
+                        .cloned(),
+                );
+                idat_written = true;
+            },
+            _ => chunks.push(chunk.clone()),
+        }
+    }
+
+    Png::from_chunks(chunks)
+}
+
+/// Checks that `png` decodes to exactly `pixels`.
+fn verify(png: &Png, pixels: &[[u16; 4]]) -> Result<()> {
+    let image = png.decode()?;
+
+    if image.to_rgba16(png.plte()?.as_ref(), png.trns()?.as_ref())? != pixels {
+        return Err(PngError::InvalidImageData {
+            reason: String::from("optimized image does not match the original pixels"),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use crate::adam7::PASSES;
+    use crate::chunk::Chunk;
+
+    use crate::filter::FilterType;
+
+    fn unoptimized(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Png {
+        let data: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                pixel(x, y)
+                    .iter()
+                    .flat_map(|&sample| [sample, sample])
+                    .collect::<Vec<u8>>()
+            })
+            .collect();
+
+        let mut png = Encoder::new(width, height, ColorType::Rgba, 16)
+            .unwrap()
+            .filter_strategy(FilterStrategy::Fixed(FilterType::None))
+            .compression_level(0)
+            .encode(&data)
+            .unwrap();
+
+        let iend = png.remove_chunk("IEND").unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec()));
+        png.append_chunk(iend);
+        png
+    }
+
+    fn assert_same_pixels(original: &Png, optimized: &Png) {
+        let pixels = original.decode().unwrap().to_rgba16(None, None).unwrap();
+        verify(optimized, &pixels).unwrap();
+    }
+
+    #[test]
+    fn test_optimize_grayscale() {
+        let png = unoptimized(16, 16, |x, y| if (x + y) % 2 == 0 { [0, 0, 0, 255] } else { [255, 255, 255, 255] });
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert_eq!(optimized.ihdr.color_type, ColorType::Grayscale);
+        assert_eq!(optimized.ihdr.bit_depth, 1);
+        assert!(optimized.optimized_size < optimized.original_size);
+        assert_same_pixels(&png, &optimized.png);
+    }
+
+    #[test]
+    fn test_optimize_palette_with_alpha() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 128], [0, 0, 255, 255], [9, 9, 9, 0], [1, 2, 3, 255]];
+        let png = unoptimized(20, 20, |x, y| colors[((x * 20 + y).wrapping_mul(2654435761) >> 16) as usize % 5]);
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert_eq!(optimized.ihdr.color_type, ColorType::Indexed);
+        assert_eq!(optimized.ihdr.bit_depth, 4);
+        assert_eq!(optimized.png.chunk_by_type("tRNS").unwrap().data(), &[128, 0]);
+        assert_same_pixels(&png, &optimized.png);
+    }
+
+    #[test]
+    fn test_optimize_drops_opaque_alpha() {
+        let png = unoptimized(32, 32, |x, y| [(x * 8) as u8, (y * 8) as u8, (x ^ y) as u8 * 8, 255]);
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert_eq!(optimized.ihdr.color_type, ColorType::Rgb);
+        assert_eq!(optimized.ihdr.bit_depth, 8);
+        assert_same_pixels(&png, &optimized.png);
+    }
+
+    #[test]
+    fn test_optimize_keeps_ancillary_chunks() {
+        let png = unoptimized(8, 8, |_, _| [10, 20, 30, 255]);
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+        let types: Vec<String> = optimized.png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+
+        assert_eq!(types, vec!["IHDR", "PLTE", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(optimized.png.chunk_by_type("ruSt").unwrap().data(), b"hidden");
+    }
+
+    #[test]
+    fn test_optimize_already_optimal() {
+        let png = unoptimized(8, 8, |_, _| [0, 0, 0, 255]);
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert!(Optimizer::new().optimize(&optimized.png).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_optimize_keeps_format_with_transparency_chunk() {
+        let data: Vec<u8> = (0..64).map(|i| (i % 4) as u8).collect();
+        let encoded = Encoder::new(8, 8, ColorType::Grayscale, 8)
+            .unwrap()
+            .compression_level(0)
+            .encode(&data)
+            .unwrap();
+        let mut chunks: Vec<Chunk> = encoded.chunks().to_vec();
+        chunks.insert(1, Chunk::new(ChunkType::TRNS, vec![0, 3]));
+        let png = Png::from_chunks(chunks);
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert_eq!(optimized.ihdr.color_type, ColorType::Grayscale);
+        assert_eq!(optimized.ihdr.bit_depth, 8);
+        assert_eq!(optimized.png.chunk_by_type("tRNS").unwrap().data(), &[0, 3]);
+        assert_eq!(optimized.png.decode().unwrap().data(), data.as_slice());
+    }
+
+    #[test]
+    fn test_optimize_interlaced_with_transparency_chunk() {
+        let (width, height) = (8, 8);
+        let pixel = |x: u32, y: u32| ((x + y) % 4) as u8;
+
+        let mut stream = Vec::new();
+        for pass in PASSES.iter() {
+            let (pass_width, pass_height) = pass.size(width, height);
+            for py in 0..pass_height {
+                stream.push(0);
+                stream.extend((0..pass_width).map(|px| pixel(pass.x_start + px * pass.x_step, pass.y_start + py * pass.y_step)));
+            }
+        }
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::none());
+        zlib.write_all(&stream).unwrap();
+
+        let ihdr = Ihdr { interlace_method: InterlaceMethod::Adam7, ..Ihdr::new(width, height, 8, ColorType::Grayscale).unwrap() };
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::TRNS, vec![0, 3]),
+            Chunk::new(ChunkType::IDAT, zlib.finish().unwrap()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]);
+
+        let optimized = Optimizer::new().optimize(&png).unwrap().unwrap();
+
+        assert_eq!(optimized.png.ihdr().unwrap().interlace_method, InterlaceMethod::None);
+        assert_eq!(optimized.png.chunk_by_type("tRNS").unwrap().data(), &[0, 3]);
+        let expected: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| pixel(x, y))).collect();
+        assert_eq!(optimized.png.decode().unwrap().data(), expected.as_slice());
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+
+/// Where a chunk type may appear relative to `PLTE` and the `IDAT`
+/// sequence (PNG spec, 5.6).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Before `PLTE` and the first `IDAT`, like `gAMA` or `iCCP`.
+    BeforePlte,
+    /// After `PLTE`, if there is one, and before the first `IDAT`, like
+    /// `tRNS` or `bKGD`.
+    AfterPlte,
+    /// Before the first `IDAT`, like `pHYs`.
+    BeforeIdat,
+    /// Anywhere between `IHDR` and `IEND`, like `tEXt` or unknown chunks.
+    Anywhere,
+}
+
+/// Where `Png::insert_chunk` puts a new chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertPosition {
+    /// Immediately before `IEND`.
+    #[default]
+    BeforeEnd,
+    /// Immediately before the first `IDAT`.
+    BeforeFirstIdat,
+    /// Immediately after `PLTE`.
+    AfterPlte,
+}
+
+/// A broken ordering or multiplicity rule, found by `validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Position of the offending chunk in the chunk list. Missing chunks are
+    /// reported at the position they should have been in.
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub reason: String,
+    /// Section of the PNG specification (third edition) with the rule.
+    pub section: &'static str,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chunk {} ({}): {} (PNG spec, section {})",
+            self.index, self.chunk_type, self.reason, self.section,
+        )
+    }
+}
+
+/// The ordering constraint on ancillary chunks of `chunk_type`. Critical
+/// chunks are `None`: known ones have fixed positions of their own, and
+/// unknown ones make the file undecodable wherever they are.
+pub fn placement(chunk_type: &ChunkType) -> Option<Placement> {
+    if chunk_type.is_critical() {
+        return None;
+    }
+
+    let placement = match &chunk_type.bytes() {
+        b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => Placement::BeforePlte,
+        b"tRNS" | b"bKGD" | b"hIST" => Placement::AfterPlte,
+        b"pHYs" | b"sPLT" | b"eXIf" | b"acTL" => Placement::BeforeIdat,
+        _ => Placement::Anywhere,
+    };
+
+    Some(placement)
+}
+
+/// Whether a file may contain more than one chunk of `chunk_type`.
+pub fn allows_multiple(chunk_type: &ChunkType) -> bool {
+    matches!(&chunk_type.bytes(), b"IDAT" | b"sPLT" | b"tEXt" | b"zTXt" | b"iTXt" | b"fdAT" | b"fcTL")
+        || !is_known(chunk_type)
+}
+
+/// Whether `chunk_type` is defined by the PNG specification.
+pub fn is_known(chunk_type: &ChunkType) -> bool {
+    matches!(
+        &chunk_type.bytes(),
+        b"IHDR" | b"PLTE" | b"IDAT" | b"IEND"
+            | b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI"
+            | b"tRNS" | b"bKGD" | b"hIST" | b"pHYs" | b"sPLT" | b"eXIf"
+            | b"tIME" | b"tEXt" | b"zTXt" | b"iTXt"
+            | b"acTL" | b"fcTL" | b"fdAT"
+    )
+}
+
+/// Checks `chunks` against the ordering and multiplicity rules of the spec
+/// and returns every violation, ordered by chunk index.
+pub fn validate(chunks: &[Chunk]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violation = |index: usize, chunk_type: ChunkType, reason: String, section: &'static str| {
+        violations.push(Violation { index, chunk_type, reason, section });
+    };
+
+    let position = |wanted: ChunkType| chunks.iter().position(|chunk| *chunk.chunk_type() == wanted);
+    let plte = position(ChunkType::PLTE);
+    let first_idat = position(ChunkType::IDAT);
+    let last_idat = chunks.iter().rposition(|chunk| *chunk.chunk_type() == ChunkType::IDAT);
+
+    match chunks.first() {
+        Some(chunk) if *chunk.chunk_type() == ChunkType::IHDR => {},
+        _ => violation(0, ChunkType::IHDR, String::from("IHDR must be the first chunk"), "5.6"),
+    }
+
+    match position(ChunkType::IEND) {
+        None => violation(chunks.len(), ChunkType::IEND, String::from("IEND is missing"), "11.2.5"),
+        Some(iend) => {
+            for (index, chunk) in chunks.iter().enumerate().skip(iend + 1) {
+                if *chunk.chunk_type() != ChunkType::IEND {
+                    violation(index, *chunk.chunk_type(), String::from("chunk comes after IEND"), "11.2.5");
+                }
+            }
+        },
+    }
+
+    match (first_idat, last_idat) {
+        (Some(first), Some(last)) => {
+            for (index, chunk) in chunks.iter().enumerate().take(last).skip(first) {
+                if *chunk.chunk_type() != ChunkType::IDAT {
+                    violation(index, *chunk.chunk_type(), String::from("chunk splits the IDAT sequence"), "11.2.4");
+                }
+            }
+        },
+        _ => violation(chunks.len(), ChunkType::IDAT, String::from("IDAT is missing"), "11.2.4"),
+    }
+
+    if let (Some(plte), Some(first_idat)) = (plte, first_idat) {
+        if plte > first_idat {
+            violation(plte, ChunkType::PLTE, String::from("PLTE must come before the first IDAT"), "11.2.3");
+        }
+    }
+
+    let color_type = chunks
+        .first()
+        .and_then(|chunk| Ihdr::try_from(chunk).ok())
+        .map(|ihdr| ihdr.color_type);
+    match (color_type, plte) {
+        (Some(ColorType::Indexed), None) => violation(
+            first_idat.unwrap_or(chunks.len()),
+            ChunkType::PLTE,
+            String::from("indexed images need a PLTE chunk"),
+            "11.2.3",
+        ),
+        (Some(color_type @ (ColorType::Grayscale | ColorType::GrayscaleAlpha)), Some(plte)) => violation(
+            plte,
+            ChunkType::PLTE,
+            format!("PLTE is not allowed for {} images", color_type),
+            "11.2.3",
+        ),
+        _ => {},
+    }
+
+    let mut seen = HashSet::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = *chunk.chunk_type();
+
+        if !seen.insert(chunk_type) && !allows_multiple(&chunk_type) {
+            violation(index, chunk_type, format!("only one {} chunk is allowed", chunk_type), "5.6");
+        }
+
+        if chunk_type.is_critical() && !is_known(&chunk_type) {
+            violation(index, chunk_type, String::from("unknown critical chunk"), "5.4");
+            continue;
+        }
+
+        let reason = match placement(&chunk_type) {
+            Some(Placement::BeforePlte) if plte.is_some_and(|plte| plte < index) => "must come before PLTE",
+            Some(Placement::AfterPlte) if plte.is_some_and(|plte| plte > index) => "must come after PLTE",
+            Some(Placement::BeforePlte | Placement::AfterPlte | Placement::BeforeIdat)
+                if first_idat.is_some_and(|idat| idat < index) =>
+            {
+                "must come before the first IDAT"
+            },
+            _ => continue,
+        };
+        violation(index, chunk_type, String::from(reason), "5.6");
+    }
+
+    violations.sort_by_key(|violation| violation.index);
+    violations
+}
+
+/// Checks that a chunk of `chunk_type` may be inserted into `chunks` in
+/// front of `index`, returning the reason if not.
+pub(crate) fn check_insert(chunks: &[Chunk], chunk_type: &ChunkType, index: usize) -> Result<(), String> {
+    let Some(placement) = placement(chunk_type) else {
+        if !is_known(chunk_type) {
+            return Err(String::from("decoders must reject files with unknown critical chunks"));
+        }
+        return Err(String::from("critical chunks are written by the encoder"));
+    };
+
+    if !allows_multiple(chunk_type) && chunks.iter().any(|chunk| chunk.chunk_type() == chunk_type) {
+        return Err(String::from("the file already has one"));
+    }
+
+    let position = |wanted: ChunkType| chunks.iter().position(|chunk| *chunk.chunk_type() == wanted);
+    let (before, after) = chunks.split_at(index);
+    let has = |chunks: &[Chunk], wanted: ChunkType| chunks.iter().any(|chunk| *chunk.chunk_type() == wanted);
+
+    if index == 0 || position(ChunkType::IHDR).is_some_and(|ihdr| index <= ihdr) {
+        return Err(String::from("it would come before IHDR"));
+    }
+
+    if position(ChunkType::IEND).is_some_and(|iend| index > iend) {
+        return Err(String::from("it would come after IEND"));
+    }
+
+    if before.last().zip(after.first()).is_some_and(|(previous, next)| {
+        *previous.chunk_type() == ChunkType::IDAT && *next.chunk_type() == ChunkType::IDAT
+    }) {
+        return Err(String::from("it would split the IDAT sequence"));
+    }
+
+    match placement {
+        Placement::BeforePlte if has(before, ChunkType::PLTE) => Err(String::from("it must come before PLTE")),
+        Placement::AfterPlte if has(after, ChunkType::PLTE) => Err(String::from("it must come after PLTE")),
+        Placement::BeforePlte | Placement::AfterPlte | Placement::BeforeIdat if has(before, ChunkType::IDAT) => {
+            Err(String::from("it must come before the first IDAT"))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunks(types: &[&str]) -> Vec<Chunk> {
+        types
+            .iter()
+            .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new()))
+            .collect()
+    }
+
+    fn chunk_type(chunk_type: &str) -> ChunkType {
+        ChunkType::from_str(chunk_type).unwrap()
+    }
+
+    #[test]
+    fn test_placement() {
+        assert_eq!(placement(&chunk_type("gAMA")), Some(Placement::BeforePlte));
+        assert_eq!(placement(&chunk_type("tRNS")), Some(Placement::AfterPlte));
+        assert_eq!(placement(&chunk_type("pHYs")), Some(Placement::BeforeIdat));
+        assert_eq!(placement(&chunk_type("ruSt")), Some(Placement::Anywhere));
+        assert_eq!(placement(&chunk_type("RuSt")), None);
+        assert_eq!(placement(&chunk_type("IDAT")), None);
+    }
+
+    #[test]
+    fn test_allows_multiple() {
+        assert!(allows_multiple(&chunk_type("tEXt")));
+        assert!(allows_multiple(&chunk_type("ruSt")));
+        assert!(!allows_multiple(&chunk_type("gAMA")));
+    }
+
+    #[test]
+    fn test_check_insert() {
+        let png = chunks(&["IHDR", "PLTE", "IDAT", "IDAT", "IEND"]);
+
+        assert!(check_insert(&png, &chunk_type("ruSt"), 4).is_ok());
+        assert!(check_insert(&png, &chunk_type("ruSt"), 1).is_ok());
+        assert!(check_insert(&png, &chunk_type("ruSt"), 0).is_err());
+        assert!(check_insert(&png, &chunk_type("ruSt"), 5).is_err());
+        assert!(check_insert(&png, &chunk_type("ruSt"), 3).is_err());
+        assert!(check_insert(&png, &chunk_type("tRNS"), 2).is_ok());
+        assert!(check_insert(&png, &chunk_type("tRNS"), 1).is_err());
+        assert!(check_insert(&png, &chunk_type("gAMA"), 1).is_ok());
+        assert!(check_insert(&png, &chunk_type("gAMA"), 2).is_err());
+        assert!(check_insert(&png, &chunk_type("pHYs"), 4).is_err());
+        assert!(check_insert(&png, &chunk_type("PLTE"), 2).is_err());
+        assert!(check_insert(&png, &chunk_type("RuSt"), 4).is_err());
+    }
+
+    fn violations(types: &[&str]) -> Vec<(usize, String, &'static str)> {
+        validate(&chunks(types))
+            .into_iter()
+            .map(|violation| (violation.index, violation.chunk_type.to_string(), violation.section))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_conforming() {
+        assert!(violations(&["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "tEXt", "IEND"]).is_empty());
+    }
+
+    #[test]
+    fn test_validate_structure() {
+        assert_eq!(violations(&["IDAT", "IHDR", "IEND"]), vec![(0, String::from("IHDR"), "5.6")]);
+        assert_eq!(violations(&["IHDR", "IDAT"]), vec![(2, String::from("IEND"), "11.2.5")]);
+        assert_eq!(violations(&["IHDR", "IEND"]), vec![(2, String::from("IDAT"), "11.2.4")]);
+        assert_eq!(
+            violations(&["IHDR", "IDAT", "IEND", "ruSt"]),
+            vec![(3, String::from("ruSt"), "11.2.5")],
+        );
+        assert_eq!(
+            violations(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]),
+            vec![(2, String::from("tEXt"), "11.2.4")],
+        );
+        assert_eq!(
+            violations(&["IHDR", "IDAT", "IEND", "IEND"]),
+            vec![(3, String::from("IEND"), "5.6")],
+        );
+    }
+
+    #[test]
+    fn test_validate_placement() {
+        assert_eq!(
+            violations(&["IHDR", "IDAT", "PLTE", "IEND"]),
+            vec![(2, String::from("PLTE"), "11.2.3")],
+        );
+        assert_eq!(
+            violations(&["IHDR", "PLTE", "gAMA", "IDAT", "sRGB", "IEND"]),
+            vec![(2, String::from("gAMA"), "5.6"), (4, String::from("sRGB"), "5.6")],
+        );
+        assert_eq!(
+            violations(&["IHDR", "bKGD", "PLTE", "IDAT", "IEND"]),
+            vec![(1, String::from("bKGD"), "5.6")],
+        );
+    }
+
+    #[test]
+    fn test_validate_unknown_critical() {
+        assert_eq!(
+            violations(&["IHDR", "IDAT", "RuSt", "ruSt", "IEND"]),
+            vec![(2, String::from("RuSt"), "5.4")],
+        );
+    }
+
+    #[test]
+    fn test_validate_multiplicity() {
+        assert_eq!(
+            violations(&["IHDR", "gAMA", "gAMA", "IDAT", "IEND"]),
+            vec![(2, String::from("gAMA"), "5.6")],
+        );
+    }
+
+    #[test]
+    fn test_validate_palette_requirement() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap().to_chunk();
+        let mut png = vec![ihdr];
+        png.extend(chunks(&["IDAT", "IEND"]));
+
+        let violations = validate(&png);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].index, violations[0].section), (1, "11.2.3"));
+        assert_eq!(
+            violations[0].to_string(),
+            "chunk 1 (PLTE): indexed images need a PLTE chunk (PNG spec, section 11.2.3)",
+        );
+    }
+
+    #[test]
+    fn test_check_insert_duplicate() {
+        let png = chunks(&["IHDR", "gAMA", "IDAT", "IEND"]);
+
+        assert!(check_insert(&png, &chunk_type("gAMA"), 1).is_err());
+        assert!(check_insert(&png, &chunk_type("tEXt"), 3).is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+
+/// The palette: up to 256 RGB entries that indexed pixels refer to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plte {
+    entries: Vec<[u8; 3]>,
+}
+
+impl TryFrom<&Chunk> for Plte {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if *chunk.chunk_type() != ChunkType::PLTE {
+            return Err(PngError::UnexpectedChunkType {
+                expected: ChunkType::PLTE,
+                found: *chunk.chunk_type(),
+            });
+        }
+
+        if chunk.data().len() % 3 != 0 {
+            return Err(invalid(format!("length {} is not a multiple of 3", chunk.data().len())));
+        }
+
+        let entries = chunk
+            .data()
+            .chunks_exact(3)
+            .map(|entry| [entry[0], entry[1], entry[2]])
+            .collect();
+
+        Self::new(entries)
+    }
+}
+
+impl Plte {
+    pub const MAX_ENTRIES: usize = 256;
+
+    pub fn new(entries: Vec<[u8; 3]>) -> Result<Self> {
+        if entries.is_empty() || entries.len() > Self::MAX_ENTRIES {
+            return Err(invalid(format!("{} entries, expected 1 to {}", entries.len(), Self::MAX_ENTRIES)));
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[[u8; 3]] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks the palette against the image header: grayscale images may
+    /// not have one, and indexed images may not have more entries than
+    /// their bit depth can address (PNG spec, 11.2.3).
+    pub fn validate(&self, ihdr: &Ihdr) -> Result<()> {
+        match ihdr.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                Err(invalid(format!("not allowed for {} images", ihdr.color_type)))
+            },
+            ColorType::Indexed if self.len() > 1 << ihdr.bit_depth => Err(invalid(format!(
+                "{} entries is more than a bit depth of {} can address",
+                self.len(),
+                ihdr.bit_depth,
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::PLTE, self.entries.concat())
+    }
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidChunkData { chunk_type: ChunkType::PLTE, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plte_from_chunk() {
+        let chunk = Chunk::new(ChunkType::PLTE, vec![1, 2, 3, 4, 5, 6]);
+        let plte = Plte::try_from(&chunk).unwrap();
+
+        assert_eq!(plte.entries(), &[[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(plte.to_chunk().as_bytes(), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_plte_invalid_length() {
+        assert!(Plte::try_from(&Chunk::new(ChunkType::PLTE, vec![1, 2, 3, 4])).is_err());
+        assert!(Plte::try_from(&Chunk::new(ChunkType::PLTE, Vec::new())).is_err());
+        assert!(Plte::try_from(&Chunk::new(ChunkType::PLTE, vec![0; 257 * 3])).is_err());
+    }
+
+    #[test]
+    fn test_plte_validate() {
+        let plte = Plte::new(vec![[0; 3]; 5]).unwrap();
+
+        assert!(plte.validate(&Ihdr::new(1, 1, 4, ColorType::Indexed).unwrap()).is_ok());
+        assert!(plte.validate(&Ihdr::new(1, 1, 2, ColorType::Indexed).unwrap()).is_err());
+        assert!(plte.validate(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()).is_ok());
+        assert!(plte.validate(&Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::{PngError, Result};
+use crate::chunk::{Chunk, ChunkRefs};
+use crate::chunk_type::ChunkType;
+use crate::decoder;
+use crate::ihdr::Ihdr;
+use crate::image::Image;
+use crate::lenient::{self, Warning};
+use crate::ordering::{self, check_insert, InsertPosition, Violation};
+use crate::plte::Plte;
+use crate::text::TextChunk;
+use crate::trns::Trns;
+
+#[derive(Debug)]
+pub struct Png {
+    chunks: Vec<Chunk>,
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = PngError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let chunks = Self::chunk_refs(bytes)?
+            .map(|chunk| chunk.map(|chunk| chunk.to_chunk()))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { chunks })
+    }
+}
+
+impl Display for Png {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for chunk in &self.chunks {
+            writeln!(f, "{} ({} bytes)", chunk.chunk_type(), chunk.length())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Png {
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        Self { chunks }
+    }
+
+    /// Checks the signature of a whole file and iterates over its chunks
+    /// without copying them, for callers that only need a few.
+    pub fn chunk_refs(bytes: &[u8]) -> Result<ChunkRefs<'_>> {
+        if bytes.len() < Self::STANDARD_HEADER.len() {
+            return Err(PngError::Truncated {
+                expected: Self::STANDARD_HEADER.len() as u64,
+                actual: bytes.len() as u64,
+                offset: Some(0),
+            });
+        }
+
+        let header = &bytes[..Self::STANDARD_HEADER.len()];
+
+        if header != Self::STANDARD_HEADER {
+            return Err(PngError::InvalidSignature { found: header.to_vec() });
+        }
+
+        Ok(ChunkRefs::new(bytes, header.len()))
+    }
+
+    /// Parses a possibly damaged file, returning every chunk that could be
+    /// recovered and what was wrong. See `lenient::parse`.
+    pub fn parse_lenient(bytes: &[u8]) -> Result<(Self, Vec<Warning>)> {
+        lenient::parse(bytes)
+    }
+
+    /// Adds `chunk` at the very end, after `IEND` if there is one. Use
+    /// `insert_chunk` to keep the file valid.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.chunks.push(chunk);
+    }
+
+    /// Inserts an ancillary chunk at `position`, refusing placements that
+    /// break the ordering rules for its type (PNG spec, 5.6).
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: InsertPosition) -> Result<()> {
+        let find = |chunk_type: ChunkType| {
+            self.chunks
+                .iter()
+                .position(|chunk| *chunk.chunk_type() == chunk_type)
+                .ok_or(PngError::ChunkNotFound { chunk_type })
+        };
+
+        let index = match position {
+            InsertPosition::BeforeEnd => find(ChunkType::IEND).unwrap_or(self.chunks.len()),
+            InsertPosition::BeforeFirstIdat => find(ChunkType::IDAT)?,
+            InsertPosition::AfterPlte => find(ChunkType::PLTE)? + 1,
+        };
+
+        check_insert(&self.chunks, chunk.chunk_type(), index)
+            .map_err(|reason| PngError::InvalidChunkOrder { chunk_type: *chunk.chunk_type(), reason })?;
+
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+
+        match self.chunks.iter().position(|chunk| *chunk.chunk_type() == chunk_type) {
+            Some(index) => Ok(self.chunks.remove(index)),
+            None => Err(PngError::ChunkNotFound { chunk_type }),
+        }
+    }
+
+    /// Checks chunk ordering and multiplicity against the spec.
+    pub fn validate(&self) -> Vec<Violation> {
+        ordering::validate(&self.chunks)
+    }
+
+    pub fn header(&self) -> &[u8; 8] {
+        &Self::STANDARD_HEADER
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        let chunk = self
+            .chunk_by_type("IHDR")
+            .ok_or(PngError::ChunkNotFound { chunk_type: ChunkType::IHDR })?;
+
+        Ihdr::try_from(chunk)
+    }
+
+    /// The palette, checked against the header.
+    pub fn plte(&self) -> Result<Option<Plte>> {
+        let Some(chunk) = self.chunk_by_type("PLTE") else {
+            return Ok(None);
+        };
+
+        let plte = Plte::try_from(chunk)?;
+        plte.validate(&self.ihdr()?)?;
+
+        Ok(Some(plte))
+    }
+
+    /// The transparency chunk, checked against the header and palette.
+    pub fn trns(&self) -> Result<Option<Trns>> {
+        let Some(chunk) = self.chunk_by_type("tRNS") else {
+            return Ok(None);
+        };
+
+        let ihdr = self.ihdr()?;
+        let trns = Trns::from_chunk(chunk, &ihdr)?;
+        trns.validate(&ihdr, self.plte()?.as_ref())?;
+
+        Ok(Some(trns))
+    }
+
+    /// Every `tEXt`, `zTXt` and `iTXt` chunk, in file order.
+    pub fn text_chunks(&self) -> Result<Vec<TextChunk>> {
+        self.chunks
+            .iter()
+            .filter(|chunk| matches!(*chunk.chunk_type(), ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT))
+            .map(TextChunk::try_from)
+            .collect()
+    }
+
+    /// The text of the first text chunk with `keyword`. Text chunks that
+    /// cannot be parsed are skipped.
+    pub fn text(&self, keyword: &str) -> Option<String> {
+        self.parsable_text_chunks()
+            .find(|chunk| chunk.keyword() == keyword)
+            .map(|chunk| chunk.text().to_string())
+    }
+
+    /// Text metadata by keyword. If a keyword appears more than once, the
+    /// first chunk wins. Text chunks that cannot be parsed are skipped.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        let mut metadata = BTreeMap::new();
+
+        for chunk in self.parsable_text_chunks().collect::<Vec<_>>().into_iter().rev() {
+            metadata.insert(chunk.keyword().to_string(), chunk.text().to_string());
+        }
+
+        metadata
+    }
+
+    fn parsable_text_chunks(&self) -> impl Iterator<Item = TextChunk> + '_ {
+        self.chunks
+            .iter()
+            .filter(|chunk| matches!(*chunk.chunk_type(), ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT))
+            .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+    }
+
+    pub fn decode(&self) -> Result<Image> {
+        decoder::decode(self)
+    }
+
+    /// Decodes to 8-bit RGBA, applying the palette and transparency.
+    pub fn decode_rgba8(&self) -> Result<Image> {
+        self.decode()?.to_rgba8(self.plte()?.as_ref(), self.trns()?.as_ref())
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.header()
+            .iter()
+            .copied()
+            .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(testing_chunks())
+    }
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        let data: Vec<u8> = data.bytes().collect();
+
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let png = Png::from_chunks(testing_chunks());
+
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_valid_from_bytes() {
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_ok());
+        assert_eq!(png.unwrap().chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .copied()
+            .chain(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(matches!(png, Err(PngError::InvalidSignature { .. })));
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let mut chunk_bytes: Vec<u8> = testing_chunks()
+            .iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        #[rustfmt::skip]
+        let mut bad_chunk = vec![
+            0, 0, 0, 5,         // length
+            32, 117, 83, 116,   // Chunk Type (bad)
+            65, 64, 65, 66, 67, // Data
+            1, 2, 3, 4, 5       // CRC (bad)
+        ];
+
+        chunk_bytes.append(&mut bad_chunk);
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunk_bytes)
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_invalid_crc_offset() {
+        let mut bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let err = Png::try_from(bytes.as_ref()).unwrap_err();
+
+        assert!(matches!(err, PngError::CrcMismatch { .. }));
+        assert_eq!(err.offset(), Some(last as u64 - 3));
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let mut bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect();
+        bytes.truncate(bytes.len() - 1);
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_list_chunks() {
+        let png = testing_png();
+        let chunks = png.chunks();
+
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[test]
+    fn test_chunk_by_type() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("FrSt").unwrap();
+
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    }
+
+    #[test]
+    fn test_append_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+
+        assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "end").unwrap(), InsertPosition::BeforeEnd).unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "idat").unwrap(), InsertPosition::BeforeFirstIdat).unwrap();
+        png.insert_chunk(chunk_from_strings("gAMA", "").unwrap(), InsertPosition::BeforeFirstIdat).unwrap();
+
+        assert_eq!(chunk_types(&png), vec!["IHDR", "ruSt", "gAMA", "IDAT", "ruSt", "IEND"]);
+        assert!(Png::try_from(png.as_bytes().as_ref()).unwrap().decode().is_ok());
+    }
+
+    #[test]
+    fn test_insert_chunk_ordering() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+
+        assert!(matches!(
+            png.insert_chunk(chunk_from_strings("pHYs", "").unwrap(), InsertPosition::BeforeEnd),
+            Err(PngError::InvalidChunkOrder { .. })
+        ));
+        assert!(matches!(
+            png.insert_chunk(chunk_from_strings("IDAT", "").unwrap(), InsertPosition::BeforeFirstIdat),
+            Err(PngError::InvalidChunkOrder { .. })
+        ));
+        assert!(matches!(
+            png.insert_chunk(chunk_from_strings("tRNS", "").unwrap(), InsertPosition::AfterPlte),
+            Err(PngError::ChunkNotFound { .. })
+        ));
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_png_validate() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.validate().is_empty());
+
+        png.append_chunk(chunk_from_strings("ruSt", "late").unwrap());
+        let violations = png.validate();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].index, 3);
+    }
+
+    #[test]
+    fn test_remove_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.remove_chunk("TeSt").unwrap();
+        let chunk = png.chunk_by_type("TeSt");
+
+        assert!(chunk.is_none());
+    }
+
+    #[test]
+    fn test_png_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]);
+
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_png_chunk_refs() {
+        let types: Vec<String> = Png::chunk_refs(&PNG_FILE)
+            .unwrap()
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+        assert!(Png::chunk_refs(&PNG_FILE[1..]).is_err());
+    }
+
+    #[test]
+    fn test_png_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+
+        assert_eq!((ihdr.width, ihdr.height), (1, 1));
+        assert!(testing_png().ihdr().is_err());
+    }
+
+    #[test]
+    fn test_png_decode() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let image = png.decode().unwrap();
+
+        assert_eq!(image.data(), &[0]);
+    }
+
+    #[test]
+    fn test_png_decode_rgba8() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let image = png.decode_rgba8().unwrap();
+
+        assert_eq!(image.data(), &[0, 0, 0, 255]);
+        assert!(png.plte().unwrap().is_none());
+        assert!(png.trns().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_png_text() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let iend = png.remove_chunk("IEND").unwrap();
+        png.append_chunk(TextChunk::new("Author", "Zoë").unwrap().to_chunk().unwrap());
+        png.append_chunk(TextChunk::new_compressed("Comment", "first").unwrap().to_chunk().unwrap());
+        png.append_chunk(TextChunk::new_international("Comment", "", "", "second", false).unwrap().to_chunk().unwrap());
+        png.append_chunk(iend);
+
+        assert_eq!(png.text("Author").as_deref(), Some("Zoë"));
+        assert_eq!(png.text("Title"), None);
+        assert_eq!(png.text_chunks().unwrap().len(), 3);
+
+        let metadata = png.metadata();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["Comment"], "first");
+    }
+
+    #[test]
+    fn test_png_text_skips_malformed_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let iend = png.remove_chunk("IEND").unwrap();
+        png.append_chunk(Chunk::new(ChunkType::TEXT, b"no separator".to_vec()));
+        png.append_chunk(TextChunk::new("Author", "Zoë").unwrap().to_chunk().unwrap());
+        png.append_chunk(iend);
+
+        assert!(png.text_chunks().is_err());
+        assert_eq!(png.text("Author").as_deref(), Some("Zoë"));
+        assert_eq!(png.metadata().len(), 1);
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let actual = png.as_bytes();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+        let _png_string = format!("{}", png);
+    }
+
+    // 1x1 grayscale PNG
+    const PNG_FILE: [u8; 67] = [
+        137, 80, 78, 71, 13, 10, 26, 10,
+        0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0, 58, 126, 155, 85,
+        0, 0, 0, 10, 73, 68, 65, 84, 120, 156, 99, 96, 0, 0, 0, 2, 0, 1, 72, 175, 164, 113,
+        0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+    ];
+}
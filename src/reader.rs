@@ -0,0 +1,283 @@
+use std::io::{ErrorKind, Read};
+
+use crate::{PngError, Result};
+use crate::checksum::ChunkCrc;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Reads a PNG one chunk at a time without buffering the whole file.
+///
+/// The signature is checked before the first chunk is returned. Each chunk
+/// is read as a length field, exactly that many data bytes and a CRC, so
+/// only the chunk currently being read is held in memory.
+#[derive(Debug)]
+pub struct ChunkReader<R: Read> {
+    inner: R,
+    position: u64,
+    signature_read: bool,
+    finished: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            position: 0,
+            signature_read: false,
+            finished: false,
+        }
+    }
+
+    /// Returns the next chunk, or `None` once the stream ends cleanly on a
+    /// chunk boundary.
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let Some((start, length, chunk_type)) = self.read_header()? else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::new();
+        let read = (&mut self.inner).take(length as u64).read_to_end(&mut data)?;
+        self.position += read as u64;
+        if read != length as usize {
+            return Err(Self::truncated(start, Chunk::DATA_BYTES as u64 + length as u64, self.position - start));
+        }
+
+        let chunk = Chunk::new(chunk_type, data);
+        self.read_crc(start, length, chunk_type, chunk.crc())?;
+
+        Ok(Some(chunk))
+    }
+
+    /// Reads past the next chunk, checking its CRC as the data streams
+    /// through instead of holding it in memory. Returns its type and length,
+    /// or `None` at the end of the stream.
+    pub fn skip_chunk(&mut self) -> Result<Option<(ChunkType, u32)>> {
+        let Some((start, length, chunk_type)) = self.read_header()? else {
+            return Ok(None);
+        };
+
+        let (crc, read) = ChunkCrc::from_reader(&chunk_type, (&mut self.inner).take(length as u64))?;
+        self.position += read;
+        if read != length as u64 {
+            return Err(Self::truncated(start, Chunk::DATA_BYTES as u64 + length as u64, self.position - start));
+        }
+
+        self.read_crc(start, length, chunk_type, crc)?;
+
+        Ok(Some((chunk_type, length)))
+    }
+
+    /// Number of bytes consumed from the underlying reader so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the length and type of the next chunk, checking the signature
+    /// first if needed. Returns the chunk's offset along with them.
+    fn read_header(&mut self) -> Result<Option<(u64, u32, ChunkType)>> {
+        if !self.signature_read {
+            self.read_signature()?;
+        }
+
+        let start = self.position;
+
+        let mut length_bytes = [0; Chunk::LENGTH_BYTES];
+        match self.fill(&mut length_bytes)? {
+            0 => return Ok(None),
+            n if n < length_bytes.len() => return Err(Self::truncated(start, Chunk::DATA_BYTES as u64, n as u64)),
+            _ => {},
+        }
+        let length = u32::from_be_bytes(length_bytes);
+
+        if length > Chunk::MAX_LENGTH {
+            return Err(PngError::InvalidLength { length: length as u64, offset: Some(start) });
+        }
+
+        let mut chunk_type_bytes = [0; Chunk::CHUNK_TYPE_BYTES];
+        self.read_exact(&mut chunk_type_bytes, start, length)?;
+        let chunk_type = ChunkType::try_from(chunk_type_bytes)
+            .map_err(|_| PngError::InvalidChunkType {
+                bytes: chunk_type_bytes.to_vec(),
+                offset: Some(start + Chunk::LENGTH_BYTES as u64),
+            })?;
+
+        Ok(Some((start, length, chunk_type)))
+    }
+
+    /// Reads the stored CRC of the chunk at `start` and compares it with
+    /// `computed`.
+    fn read_crc(&mut self, start: u64, length: u32, chunk_type: ChunkType, computed: u32) -> Result<()> {
+        let crc_offset = self.position;
+        let mut crc_bytes = [0; Chunk::CRC_BYTES];
+        self.read_exact(&mut crc_bytes, start, length)?;
+        let crc = u32::from_be_bytes(crc_bytes);
+
+        if crc != computed {
+            return Err(PngError::CrcMismatch {
+                chunk_type,
+                expected: computed,
+                actual: crc,
+                offset: Some(crc_offset),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_signature(&mut self) -> Result<()> {
+        let mut header = [0; 8];
+        let read = self.fill(&mut header)?;
+
+        if read < header.len() {
+            return Err(Self::truncated(0, header.len() as u64, read as u64));
+        }
+
+        if header != Png::STANDARD_HEADER {
+            return Err(PngError::InvalidSignature { found: header.to_vec() });
+        }
+
+        self.signature_read = true;
+        Ok(())
+    }
+
+    /// Reads part of the chunk starting at `start`, reporting a short read as
+    /// a truncated chunk.
+    fn read_exact(&mut self, buf: &mut [u8], start: u64, length: u32) -> Result<()> {
+        let read = self.fill(buf)?;
+
+        if read < buf.len() {
+            return Err(Self::truncated(start, Chunk::DATA_BYTES as u64 + length as u64, self.position - start));
+        }
+
+        Ok(())
+    }
+
+    /// Reads until `buf` is full or the stream ends, returning the number of
+    /// bytes read.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        self.position += filled as u64;
+        Ok(filled)
+    }
+
+    fn truncated(start: u64, expected: u64, actual: u64) -> PngError {
+        PngError::Truncated { expected, actual, offset: Some(start) }
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    /// Stops after the end of the stream or the first error, since the
+    /// reader cannot know where the next chunk starts once one is corrupt.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.read_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ];
+
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_bytes();
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice())
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].chunk_type().to_string(), "FrSt");
+        assert_eq!(chunks[1].data_as_string().unwrap(), "I am the last chunk");
+    }
+
+    #[test]
+    fn test_read_empty_stream() {
+        let mut reader = ChunkReader::new(&Png::STANDARD_HEADER[..]);
+
+        assert!(reader.read_chunk().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 13;
+
+        let mut reader = ChunkReader::new(bytes.as_slice());
+
+        assert!(reader.read_chunk().is_err());
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let mut bytes = testing_bytes();
+        bytes.truncate(bytes.len() - 6);
+
+        let results: Vec<Result<Chunk>> = ChunkReader::new(bytes.as_slice()).collect();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(PngError::Truncated { offset: Some(40), .. })));
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let results: Vec<Result<Chunk>> = ChunkReader::new(bytes.as_slice()).collect();
+
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(PngError::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_skip_chunk() {
+        let mut bytes = testing_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice());
+
+        assert_eq!(reader.skip_chunk().unwrap(), Some((ChunkType::from_str("FrSt").unwrap(), 20)));
+        assert_eq!(reader.skip_chunk().unwrap(), Some((ChunkType::from_str("LASt").unwrap(), 19)));
+        assert_eq!(reader.skip_chunk().unwrap(), None);
+
+        bytes[8 + 8] ^= 0xff;
+        let mut reader = ChunkReader::new(bytes.as_slice());
+
+        assert!(matches!(reader.skip_chunk(), Err(PngError::CrcMismatch { offset: Some(36), .. })));
+    }
+}
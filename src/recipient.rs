@@ -0,0 +1,246 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::{PngError, Result};
+
+/// Prefix of a textual public key.
+pub const RECIPIENT_PREFIX: &str = "pngrs-pub-";
+
+/// Prefix of a textual secret key.
+pub const IDENTITY_PREFIX: &str = "PNGRS-SECRET-KEY-";
+
+/// Length of a symmetric key, both the file key and the keys wrapping it.
+pub const KEY_LENGTH: usize = 32;
+
+/// Length of one wrapped file key: the ephemeral public key followed by the
+/// encrypted file key and its tag.
+pub const STANZA_LENGTH: usize = 32 + KEY_LENGTH + 16;
+
+const WRAP_INFO: &[u8] = b"png-rs X25519 v1";
+
+/// An X25519 public key that a message can be encrypted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+/// An X25519 secret key that unwraps file keys for its `Recipient`.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Recipient {
+    /// Wraps `file_key` for this recipient with a fresh ephemeral key, as in
+    /// age: the X25519 shared secret goes through HKDF-SHA256 salted with
+    /// both public keys and the result encrypts the file key.
+    pub fn wrap(&self, file_key: &[u8; KEY_LENGTH]) -> Result<[u8; STANZA_LENGTH]> {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&self.0);
+
+        if !shared.was_contributory() {
+            return Err(PngError::InvalidArgument { reason: String::from("recipient is a low-order point") });
+        }
+
+        let key = wrap_key(shared.as_bytes(), &ephemeral_public, &self.0);
+        let wrapped = ChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(&Nonce::default(), file_key.as_ref())
+            .expect("a 32-byte key always fits");
+
+        let mut stanza = [0; STANZA_LENGTH];
+        stanza[..32].copy_from_slice(ephemeral_public.as_bytes());
+        stanza[32..].copy_from_slice(&wrapped);
+        Ok(stanza)
+    }
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn to_recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// The secret key as text. Not a `Display` impl, so that it cannot end up
+    /// in a log line by accident.
+    pub fn to_secret_string(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!("{}{}", IDENTITY_PREFIX, URL_SAFE_NO_PAD.encode(self.0.as_bytes())))
+    }
+
+    /// Recovers the file key from a stanza made by `Recipient::wrap`, or
+    /// `None` if it was wrapped for someone else.
+    pub fn unwrap(&self, stanza: &[u8; STANZA_LENGTH]) -> Option<Zeroizing<[u8; KEY_LENGTH]>> {
+        let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&stanza[..32]).unwrap());
+        let shared = self.0.diffie_hellman(&ephemeral_public);
+
+        if !shared.was_contributory() {
+            return None;
+        }
+
+        let key = wrap_key(shared.as_bytes(), &ephemeral_public, &PublicKey::from(&self.0));
+        let file_key = Zeroizing::new(
+            ChaCha20Poly1305::new(key.as_ref().into())
+                .decrypt(&Nonce::default(), &stanza[32..])
+                .ok()?,
+        );
+
+        Some(Zeroizing::new(file_key.as_slice().try_into().ok()?))
+    }
+
+    /// Reads every secret key in an identity file. See `parse_key_file`.
+    pub fn parse_file(contents: &str) -> Result<Vec<Identity>> {
+        parse_key_file(contents, Identity::from_str)
+    }
+
+    /// The contents of an identity file for this key, with the public key
+    /// in a comment.
+    pub fn to_file(&self) -> Zeroizing<String> {
+        key_file("public key", &self.to_recipient(), &self.to_secret_string())
+    }
+}
+
+impl Debug for Identity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Identity").field(&self.to_recipient()).finish()
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", RECIPIENT_PREFIX, URL_SAFE_NO_PAD.encode(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(PublicKey::from(decode_key(s, RECIPIENT_PREFIX, "public")?)))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(decode_key(s, IDENTITY_PREFIX, "secret")?);
+
+        Ok(Self(StaticSecret::from(*bytes)))
+    }
+}
+
+/// Decodes a 32-byte key written as `prefix` followed by unpadded URL-safe
+/// base64.
+pub(crate) fn decode_key(s: &str, prefix: &str, kind: &str) -> Result<[u8; 32]> {
+    let invalid = || PngError::InvalidArgument { reason: format!("invalid {} key, expected {}...", kind, prefix) };
+
+    let encoded = s.strip_prefix(prefix).ok_or_else(invalid)?;
+    let bytes = Zeroizing::new(URL_SAFE_NO_PAD.decode(encoded).map_err(|_| invalid())?);
+
+    bytes.as_slice().try_into().map_err(|_| invalid())
+}
+
+/// Parses every key in a key file with `parse`, skipping blank lines and
+/// `#` comments. Errors about a key name its line.
+pub(crate) fn parse_key_file<T>(contents: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            parse(line).map_err(|e| match e {
+                PngError::InvalidArgument { reason } => {
+                    PngError::InvalidArgument { reason: format!("line {}: {}", index + 1, reason) }
+                },
+                e => e,
+            })
+        })
+        .collect()
+}
+
+/// A key file holding `secret`, with `public` in a comment labelled `label`.
+pub(crate) fn key_file(label: &str, public: &impl Display, secret: &str) -> Zeroizing<String> {
+    Zeroizing::new(format!("# {}: {}\n{}\n", label, public, secret))
+}
+
+fn wrap_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Zeroizing<[u8; KEY_LENGTH]> {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, key.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let identity = Identity::generate();
+        let other = Identity::generate();
+        let file_key = [7; KEY_LENGTH];
+
+        let stanza = identity.to_recipient().wrap(&file_key).unwrap();
+
+        assert_eq!(*identity.unwrap(&stanza).unwrap(), file_key);
+        assert!(other.unwrap(&stanza).is_none());
+    }
+
+    #[test]
+    fn test_unwrap_tampered() {
+        let identity = Identity::generate();
+        let stanza = identity.to_recipient().wrap(&[7; KEY_LENGTH]).unwrap();
+
+        for index in [0, 40, STANZA_LENGTH - 1] {
+            let mut tampered = stanza;
+            tampered[index] ^= 0x01;
+
+            assert!(identity.unwrap(&tampered).is_none(), "byte {}", index);
+        }
+    }
+
+    #[test]
+    fn test_key_strings() {
+        let identity = Identity::generate();
+        let recipient = identity.to_recipient();
+
+        assert!(recipient.to_string().starts_with(RECIPIENT_PREFIX));
+        assert_eq!(Recipient::from_str(&recipient.to_string()).unwrap(), recipient);
+        assert_eq!(Identity::from_str(&identity.to_secret_string()).unwrap().to_recipient(), recipient);
+        assert!(!format!("{:?}", identity).contains(IDENTITY_PREFIX));
+
+        assert!(matches!(Recipient::from_str("pngrs-pub-short"), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(Recipient::from_str(&identity.to_secret_string()), Err(PngError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_identity_file() {
+        let first = Identity::generate();
+        let second = Identity::generate();
+        let contents = format!("{}\n{}", *first.to_file(), *second.to_file());
+
+        let identities = Identity::parse_file(&contents).unwrap();
+
+        assert_eq!(identities.len(), 2);
+        assert_eq!(identities[1].to_recipient(), second.to_recipient());
+        assert!(matches!(
+            Identity::parse_file(&format!("{}not a key\n", *first.to_file())),
+            Err(PngError::InvalidArgument { reason }) if reason.starts_with("line 3: ")
+        ));
+    }
+}
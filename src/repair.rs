@@ -0,0 +1,279 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::{PngError, Result};
+use crate::checksum::ChunkCrc;
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::lenient::find_chunk_start;
+use crate::ordering::is_known;
+use crate::png::Png;
+
+/// One fix made by `repair`. Offsets are those of the chunk in the damaged
+/// input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    CrcFixed { chunk_type: ChunkType, offset: u64, stored: u32, computed: u32 },
+    /// The length field did not match where the next chunk starts.
+    LengthFixed { chunk_type: ChunkType, offset: u64, stored: u32, actual: u32 },
+    IendAdded,
+    /// Bytes in the middle of the file that did not form a chunk.
+    BytesSkipped { count: u64, offset: u64 },
+    TrailingBytesDropped { count: u64, offset: u64 },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CrcFixed { chunk_type, offset, stored, computed } => write!(
+                f,
+                "{} chunk at offset {}: CRC {:#010x} replaced with {:#010x}",
+                chunk_type, offset, stored, computed,
+            ),
+            Change::LengthFixed { chunk_type, offset, stored, actual } => write!(
+                f,
+                "{} chunk at offset {}: length {} replaced with {}",
+                chunk_type, offset, stored, actual,
+            ),
+            Change::IendAdded => write!(f, "missing IEND chunk added"),
+            Change::BytesSkipped { count, offset } => {
+                write!(f, "{} unreadable bytes at offset {} dropped", count, offset)
+            },
+            Change::TrailingBytesDropped { count, offset } => {
+                write!(f, "{} trailing bytes at offset {} dropped", count, offset)
+            },
+        }
+    }
+}
+
+/// Recovers a PNG damaged in transit.
+///
+/// CRCs are recomputed, length fields that disagree with the chunk
+/// boundaries are corrected by searching for the next known chunk type, a
+/// missing `IEND` is added, bytes that cannot be read as a chunk are skipped
+/// up to the next chunk that can, and anything after `IEND` or the last
+/// recoverable chunk is dropped. Returns the repaired file and every change
+/// made, which is empty if nothing was wrong.
+pub fn repair(bytes: &[u8]) -> Result<(Png, Vec<Change>)> {
+    let header_length = Png::STANDARD_HEADER.len();
+
+    if bytes.len() < header_length {
+        return Err(PngError::Truncated {
+            expected: header_length as u64,
+            actual: bytes.len() as u64,
+            offset: Some(0),
+        });
+    }
+
+    if bytes[..header_length] != Png::STANDARD_HEADER {
+        return Err(PngError::InvalidSignature { found: bytes[..header_length].to_vec() });
+    }
+
+    let mut chunks = Vec::new();
+    let mut changes = Vec::new();
+    let mut offset = header_length;
+
+    while offset < bytes.len() {
+        let ended = chunks.last().is_some_and(|chunk: &Chunk| *chunk.chunk_type() == ChunkType::IEND);
+        let fits = bytes.len() - offset >= Chunk::DATA_BYTES;
+        let chunk_type = chunk_type_at(bytes, offset + Chunk::LENGTH_BYTES).filter(|_| fits);
+
+        let (Some(chunk_type), false) = (chunk_type, ended) else {
+            let next = if ended { None } else { find_chunk_start(bytes, offset + 1) };
+            match next {
+                Some(next) => {
+                    changes.push(Change::BytesSkipped { count: (next - offset) as u64, offset: offset as u64 });
+                    offset = next;
+                    continue;
+                },
+                None => {
+                    changes.push(Change::TrailingBytesDropped {
+                        count: (bytes.len() - offset) as u64,
+                        offset: offset as u64,
+                    });
+                    break;
+                },
+            }
+        };
+
+        let stored_length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let data_start = offset + Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES;
+        let end = match chunk_end(bytes, offset) {
+            Some(end) => end,
+            None => {
+                let end = find_chunk_end(bytes, offset, &chunk_type);
+                changes.push(Change::LengthFixed {
+                    chunk_type,
+                    offset: offset as u64,
+                    stored: stored_length,
+                    actual: (end - data_start - Chunk::CRC_BYTES) as u32,
+                });
+                end
+            },
+        };
+
+        let data = bytes[data_start..end - Chunk::CRC_BYTES].to_vec();
+        let stored = u32::from_be_bytes(bytes[end - Chunk::CRC_BYTES..end].try_into().unwrap());
+        let chunk = Chunk::new(chunk_type, data);
+
+        if chunk.crc() != stored {
+            changes.push(Change::CrcFixed { chunk_type, offset: offset as u64, stored, computed: chunk.crc() });
+        }
+
+        chunks.push(chunk);
+        offset = end;
+    }
+
+    if !chunks.iter().any(|chunk| *chunk.chunk_type() == ChunkType::IEND) {
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        changes.push(Change::IendAdded);
+    }
+
+    Ok((Png::from_chunks(chunks), changes))
+}
+
+/// The chunk type at `offset`, if there is a valid one.
+fn chunk_type_at(bytes: &[u8], offset: usize) -> Option<ChunkType> {
+    let type_bytes: [u8; 4] = bytes.get(offset..offset + Chunk::CHUNK_TYPE_BYTES)?.try_into().ok()?;
+
+    ChunkType::try_from(type_bytes).ok()
+}
+
+/// Where the chunk at `offset` ends according to its length field, if that
+/// is plausible: the chunk fits, and either its CRC matches or it is
+/// followed by the end of the input, a known chunk type or an intact chunk.
+fn chunk_end(bytes: &[u8], offset: usize) -> Option<usize> {
+    let (chunk, used) = ChunkRef::parse_unverified(&bytes[offset..]).ok()?;
+    let end = offset + used;
+
+    let followed = end == bytes.len()
+        || chunk_type_at(bytes, end + Chunk::LENGTH_BYTES).is_some_and(|next| is_known(&next))
+        || ChunkRef::parse(&bytes[end..]).is_ok();
+
+    (followed || chunk.crc_matches()).then_some(end)
+}
+
+/// Finds where the chunk at `offset` really ends: preferably just before a
+/// known chunk type with a matching CRC, otherwise before the first known
+/// chunk type, otherwise at the end of the input. The chunk must fit. The
+/// CRC is computed in one pass as the candidate end advances.
+fn find_chunk_end(bytes: &[u8], offset: usize, chunk_type: &ChunkType) -> usize {
+    let data_start = offset + Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES;
+    let mut crc = ChunkCrc::new(chunk_type);
+    let mut hashed = data_start;
+    let mut first = None;
+
+    for end in offset + Chunk::DATA_BYTES..bytes.len() {
+        if !chunk_type_at(bytes, end + Chunk::LENGTH_BYTES).is_some_and(|next| is_known(&next)) {
+            continue;
+        }
+
+        let data_end = end - Chunk::CRC_BYTES;
+        crc.update(&bytes[hashed..data_end]);
+        hashed = data_end;
+
+        let stored = u32::from_be_bytes(bytes[data_end..end].try_into().unwrap());
+        if crc.clone().finalize() == stored {
+            return end;
+        }
+        first.get_or_insert(end);
+    }
+
+    first.unwrap_or(bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("ruSt", b"secret message"),
+            chunk("IDAT", &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_repair_intact() {
+        let bytes = testing_png().as_bytes();
+        let (png, changes) = repair(&bytes).unwrap();
+
+        assert!(changes.is_empty());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_repair_crc() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[33 + 8 + 3] ^= 0x20;
+
+        let (png, changes) = repair(&bytes).unwrap();
+
+        assert!(matches!(changes[..], [Change::CrcFixed { offset: 33, .. }]));
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"secRet message");
+        assert!(Png::try_from(png.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_repair_length() {
+        for length in [3u32, 40, 0xffff_ffff] {
+            let mut bytes = testing_png().as_bytes();
+            bytes[33..37].copy_from_slice(&length.to_be_bytes());
+
+            let (png, changes) = repair(&bytes).unwrap();
+
+            assert_eq!(
+                changes,
+                vec![Change::LengthFixed {
+                    chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                    offset: 33,
+                    stored: length,
+                    actual: 14,
+                }],
+            );
+            assert_eq!(png.as_bytes(), testing_png().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_repair_missing_iend_and_trailing_bytes() {
+        let bytes = testing_png().as_bytes();
+        let without_iend = &bytes[..bytes.len() - Chunk::DATA_BYTES];
+        let (png, changes) = repair(without_iend).unwrap();
+
+        assert_eq!(changes, vec![Change::IendAdded]);
+        assert_eq!(png.as_bytes(), bytes);
+
+        let mut with_junk = bytes.clone();
+        with_junk.extend_from_slice(b"junk after the end");
+        let (png, changes) = repair(&with_junk).unwrap();
+
+        assert_eq!(changes, vec![Change::TrailingBytesDropped { count: 18, offset: bytes.len() as u64 }]);
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_repair_corrupt_chunk_type() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[33 + 4] = b'!';
+
+        let (png, changes) = repair(&bytes).unwrap();
+
+        assert_eq!(changes, vec![Change::BytesSkipped { count: 26, offset: 33 }]);
+        let chunk_types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(chunk_types, ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_repair_invalid_signature() {
+        assert!(matches!(repair(b"GIF89a\0\0"), Err(PngError::InvalidSignature { .. })));
+        assert!(matches!(repair(b"\x89PNG"), Err(PngError::Truncated { .. })));
+    }
+}
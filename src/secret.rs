@@ -0,0 +1,350 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::{PngError, Result};
+use crate::recipient::{Identity, Recipient, KEY_LENGTH, STANZA_LENGTH};
+
+/// Marks chunk data as an encrypted message. The first byte has its high bit
+/// set so that it is never mistaken for ASCII text.
+pub const MAGIC: [u8; 4] = *b"\x89ENC";
+
+/// Version of the layouts described on `encrypt_with` and `encrypt_to`.
+pub const VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const TAG_LENGTH: usize = 16;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 12 + SALT_LENGTH + NONCE_LENGTH;
+
+/// How the key of an encrypted message is obtained, stored after the
+/// version byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Derived from a passphrase with Argon2id.
+    Passphrase = 1,
+    /// A random file key, wrapped for each of a list of X25519 recipients.
+    Recipients = 2,
+}
+
+/// Upper bounds on the Argon2 cost parameters, four times the defaults
+/// (76 MiB, eight passes, four lanes), so a crafted header cannot make the
+/// reader spend unbounded memory or time.
+const MAX_MEMORY_KIB: u32 = 4 * Params::DEFAULT_M_COST;
+const MAX_ITERATIONS: u32 = 4 * Params::DEFAULT_T_COST;
+const MAX_PARALLELISM: u32 = 4 * Params::DEFAULT_P_COST;
+
+/// Argon2id cost parameters. They are stored in the header, so stronger
+/// settings can be chosen later without breaking old files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// The Argon2id parameters recommended by OWASP: 19 MiB, two passes.
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Whether the costs are at most `MAX_MEMORY_KIB`, `MAX_ITERATIONS` and
+    /// `MAX_PARALLELISM`, and so will be accepted by `decrypt`.
+    pub fn is_within_limits(&self) -> bool {
+        self.memory_kib <= MAX_MEMORY_KIB && self.iterations <= MAX_ITERATIONS && self.parallelism <= MAX_PARALLELISM
+    }
+}
+
+/// Whether chunk data looks like the output of `encrypt` or `encrypt_to`.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// The scheme of an encrypted message, after checking its magic and version.
+pub fn scheme(data: &[u8]) -> Result<Scheme> {
+    if data.len() < MAGIC.len() + 2 || !is_encrypted(data) {
+        return Err(decryption_error("not an encrypted message"));
+    }
+
+    match (data[4], data[5]) {
+        (VERSION, scheme) if scheme == Scheme::Passphrase as u8 => Ok(Scheme::Passphrase),
+        (VERSION, scheme) if scheme == Scheme::Recipients as u8 => Ok(Scheme::Recipients),
+        (VERSION, scheme) => Err(decryption_error(&format!("unsupported scheme {}", scheme))),
+        (version, _) => Err(decryption_error(&format!("unsupported version {}", version))),
+    }
+}
+
+/// Encrypts `message` with a key derived from `passphrase`, using the
+/// default `KdfParams`.
+pub fn encrypt(message: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    encrypt_with(message, passphrase, KdfParams::default())
+}
+
+/// Encrypts `message` with XChaCha20-Poly1305 under an Argon2id key derived
+/// from `passphrase` and a random salt.
+///
+/// The result is `MAGIC`, the version, the scheme, the three cost
+/// parameters as big-endian `u32`s, the salt, the nonce and then the
+/// ciphertext with its tag. Everything before the ciphertext is
+/// authenticated too.
+pub fn encrypt_with(message: &[u8], passphrase: &[u8], params: KdfParams) -> Result<Vec<u8>> {
+    if !params.is_within_limits() {
+        return Err(PngError::InvalidArgument { reason: String::from("key derivation cost is too high to decrypt") });
+    }
+
+    let mut salt = [0; SALT_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut data = Vec::with_capacity(HEADER_LENGTH + message.len() + TAG_LENGTH);
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.push(Scheme::Passphrase as u8);
+    data.extend_from_slice(&params.memory_kib.to_be_bytes());
+    data.extend_from_slice(&params.iterations.to_be_bytes());
+    data.extend_from_slice(&params.parallelism.to_be_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    seal(data, &key, &nonce, message)
+}
+
+/// Encrypts `message` so that any one of `recipients` can read it.
+///
+/// A random file key encrypts the message with XChaCha20-Poly1305 and is
+/// wrapped for each recipient by `Recipient::wrap`. The result is `MAGIC`,
+/// the version, the scheme, the number of recipients as a big-endian `u16`,
+/// one stanza per recipient, the nonce and then the ciphertext with its tag.
+/// The tag covers the stanzas too, so none can be swapped or dropped.
+pub fn encrypt_to(message: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    let count = u16::try_from(recipients.len())
+        .ok()
+        .filter(|&count| count > 0)
+        .ok_or_else(|| PngError::InvalidArgument {
+            reason: format!("expected 1 to {} recipients, got {}", u16::MAX, recipients.len()),
+        })?;
+
+    let mut file_key = Zeroizing::new([0; KEY_LENGTH]);
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(file_key.as_mut());
+    OsRng.fill_bytes(&mut nonce);
+
+    let header_length = MAGIC.len() + 4 + recipients.len() * STANZA_LENGTH + NONCE_LENGTH;
+    let mut data = Vec::with_capacity(header_length + message.len() + TAG_LENGTH);
+    data.extend_from_slice(&MAGIC);
+    data.push(VERSION);
+    data.push(Scheme::Recipients as u8);
+    data.extend_from_slice(&count.to_be_bytes());
+    for recipient in recipients {
+        data.extend_from_slice(&recipient.wrap(&file_key)?);
+    }
+    data.extend_from_slice(&nonce);
+
+    seal(data, &file_key, &nonce, message)
+}
+
+/// Decrypts data produced by `encrypt`. Fails with `PngError::Decryption`
+/// if the header is malformed, the passphrase is wrong or any byte was
+/// changed.
+pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    if scheme(data)? != Scheme::Passphrase {
+        return Err(decryption_error("encrypted to recipients, not with a passphrase"));
+    }
+    if data.len() < HEADER_LENGTH + TAG_LENGTH {
+        return Err(decryption_error("truncated header"));
+    }
+
+    let (header, ciphertext) = data.split_at(HEADER_LENGTH);
+
+    let field = |index: usize| u32::from_be_bytes(header[6 + 4 * index..10 + 4 * index].try_into().unwrap());
+    let params = KdfParams { memory_kib: field(0), iterations: field(1), parallelism: field(2) };
+
+    if !params.is_within_limits() {
+        return Err(decryption_error("key derivation cost is too high"));
+    }
+
+    let salt = &header[18..18 + SALT_LENGTH];
+    let nonce = &header[18 + SALT_LENGTH..];
+    let key = derive_key(passphrase, salt, params).map_err(|e| decryption_error(&e.to_string()))?;
+
+    open(header, &key, nonce, ciphertext).ok_or_else(|| decryption_error("wrong passphrase or the data was modified"))
+}
+
+/// Decrypts data produced by `encrypt_to` with whichever of `identities`
+/// it was encrypted to. Fails with `PngError::Decryption` if none of them
+/// is a recipient or any byte was changed.
+pub fn decrypt_with(data: &[u8], identities: &[Identity]) -> Result<Vec<u8>> {
+    if scheme(data)? != Scheme::Recipients {
+        return Err(decryption_error("encrypted with a passphrase, not to recipients"));
+    }
+
+    let count = data.get(6..8).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize).unwrap_or(0);
+    let header_length = MAGIC.len() + 4 + count * STANZA_LENGTH + NONCE_LENGTH;
+    if count == 0 || data.len() < header_length + TAG_LENGTH {
+        return Err(decryption_error("truncated header"));
+    }
+
+    let (header, ciphertext) = data.split_at(header_length);
+    let nonce = &header[header_length - NONCE_LENGTH..];
+    let file_key = header[8..header_length - NONCE_LENGTH]
+        .chunks_exact(STANZA_LENGTH)
+        .flat_map(|stanza| identities.iter().filter_map(|identity| identity.unwrap(stanza.try_into().unwrap())))
+        .next()
+        .ok_or_else(|| decryption_error("no identity matches any recipient"))?;
+
+    open(header, &file_key, nonce, ciphertext).ok_or_else(|| decryption_error("the data was modified"))
+}
+
+/// Appends the ciphertext of `message` to `header`, which is authenticated
+/// along with it.
+fn seal(mut header: Vec<u8>, key: &[u8; KEY_LENGTH], nonce: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(XNonce::from_slice(nonce), Payload { msg: message, aad: &header })
+        .map_err(|_| PngError::InvalidArgument { reason: String::from("message is too long to encrypt") })?;
+
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+fn open(header: &[u8], key: &[u8; KEY_LENGTH], nonce: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .ok()
+}
+
+pub(crate) fn derive_key(passphrase: &[u8], salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; KEY_LENGTH]>> {
+    let invalid = |e: argon2::Error| PngError::InvalidArgument { reason: format!("key derivation: {}", e) };
+
+    let params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LENGTH))
+        .map_err(invalid)?;
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, key.as_mut())
+        .map_err(invalid)?;
+
+    Ok(key)
+}
+
+fn decryption_error(reason: &str) -> PngError {
+    PngError::Decryption { reason: String::from(reason) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+
+    /// Cheap parameters so the tests run quickly in debug builds.
+    const PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let data = encrypt_with(b"meet at the docks", b"correct horse", PARAMS).unwrap();
+
+        assert!(is_encrypted(&data));
+        assert_eq!(data.len(), HEADER_LENGTH + 17 + TAG_LENGTH);
+        assert!(!data.windows(4).any(|window| window == b"meet"));
+        assert_eq!(decrypt(&data, b"correct horse").unwrap(), b"meet at the docks");
+    }
+
+    #[test]
+    fn test_encrypt_is_randomised() {
+        let first = encrypt_with(b"message", b"passphrase", PARAMS).unwrap();
+        let second = encrypt_with(b"message", b"passphrase", PARAMS).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_decrypt_wrong_passphrase() {
+        let data = encrypt_with(b"message", b"passphrase", PARAMS).unwrap();
+
+        assert!(matches!(decrypt(&data, b"passphrasf"), Err(PngError::Decryption { .. })));
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let data = encrypt_with(b"message", b"passphrase", PARAMS).unwrap();
+
+        for index in [6, 20, HEADER_LENGTH - 1, HEADER_LENGTH, data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[index] ^= 0x01;
+
+            assert!(matches!(decrypt(&tampered, b"passphrase"), Err(PngError::Decryption { .. })), "byte {}", index);
+        }
+
+        assert!(matches!(decrypt(&data[..data.len() - 1], b"passphrase"), Err(PngError::Decryption { .. })));
+    }
+
+    #[test]
+    fn test_decrypt_invalid_header() {
+        let mut data = encrypt_with(b"message", b"passphrase", PARAMS).unwrap();
+
+        assert!(matches!(decrypt(b"plain text", b"passphrase"), Err(PngError::Decryption { .. })));
+
+        data[4] = 2;
+        assert!(matches!(decrypt(&data, b"passphrase"), Err(PngError::Decryption { reason }) if reason.contains("version")));
+
+        data[4] = VERSION;
+        data[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(decrypt(&data, b"passphrase"), Err(PngError::Decryption { reason }) if reason.contains("cost")));
+
+        data[6..10].copy_from_slice(&PARAMS.memory_kib.to_be_bytes());
+        data[14..18].copy_from_slice(&(MAX_PARALLELISM + 1).to_be_bytes());
+        assert!(matches!(decrypt(&data, b"passphrase"), Err(PngError::Decryption { reason }) if reason.contains("cost")));
+    }
+
+    #[test]
+    fn test_kdf_limits() {
+        assert!(KdfParams::default().is_within_limits());
+        assert!(!KdfParams { memory_kib: MAX_MEMORY_KIB + 1, ..PARAMS }.is_within_limits());
+        assert!(!KdfParams { iterations: MAX_ITERATIONS + 1, ..PARAMS }.is_within_limits());
+        assert!(matches!(
+            encrypt_with(b"message", b"passphrase", KdfParams { parallelism: MAX_PARALLELISM + 1, ..PARAMS }),
+            Err(PngError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_to_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        let data = encrypt_to(b"meet at the docks", &[alice.to_recipient(), bob.to_recipient()]).unwrap();
+
+        assert_eq!(scheme(&data).unwrap(), Scheme::Recipients);
+        assert_eq!(decrypt_with(&data, slice::from_ref(&alice)).unwrap(), b"meet at the docks");
+        assert_eq!(decrypt_with(&data, &[eve.clone(), bob]).unwrap(), b"meet at the docks");
+        assert!(matches!(decrypt_with(&data, &[eve]), Err(PngError::Decryption { .. })));
+        assert!(matches!(decrypt(&data, b"passphrase"), Err(PngError::Decryption { .. })));
+        assert!(matches!(encrypt_to(b"message", &[]), Err(PngError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_decrypt_with_tampered() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let data = encrypt_to(b"message", &[alice.to_recipient(), bob.to_recipient()]).unwrap();
+
+        // Bob's stanza and the count are covered by the tag even when Alice decrypts.
+        for index in [7, 8 + STANZA_LENGTH, data.len() - 20, data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[index] ^= 0x01;
+
+            assert!(matches!(decrypt_with(&tampered, slice::from_ref(&alice)), Err(PngError::Decryption { .. })), "byte {}", index);
+        }
+    }
+}
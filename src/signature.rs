@@ -0,0 +1,447 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ordering::InsertPosition;
+use crate::png::Png;
+use crate::recipient::{decode_key, key_file, parse_key_file};
+
+/// Prefix of a textual verifying key.
+pub const VERIFYING_KEY_PREFIX: &str = "pngrs-sign-pub-";
+
+/// Prefix of a textual signing key.
+pub const SIGNING_KEY_PREFIX: &str = "PNGRS-SIGN-SECRET-KEY-";
+
+/// Version of the signature chunk layout described on `sign`.
+pub const VERSION: u8 = 1;
+
+/// Chunks that every signature covers. `IEND` is left out as it has no data.
+pub const COVERED: [ChunkType; 3] = [ChunkType::IHDR, ChunkType::PLTE, ChunkType::IDAT];
+
+const DOMAIN: &[u8] = b"png-rs signature v1\0";
+const KEY_ID_LENGTH: usize = 8;
+
+/// First 8 bytes of the SHA-256 of a verifying key, stored in the signature
+/// chunk to tell which key made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyId([u8; KEY_ID_LENGTH]);
+
+/// An Ed25519 key that signs images.
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+/// An Ed25519 key that checks signatures made by its `SigningKey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+/// A parsed signature chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureChunk {
+    pub key_id: KeyId,
+    /// Ancillary chunk types covered on top of `COVERED`.
+    pub chunk_types: Vec<ChunkType>,
+    signature: Signature,
+}
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        Self(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
+    /// The secret key as text, kept out of `Display` like
+    /// `Identity::to_secret_string`.
+    pub fn to_secret_string(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!("{}{}", SIGNING_KEY_PREFIX, URL_SAFE_NO_PAD.encode(self.0.as_bytes())))
+    }
+
+    /// Reads the only signing key in a key file. See `parse_key_file`.
+    pub fn parse_file(contents: &str) -> Result<SigningKey> {
+        let mut keys = parse_key_file(contents, SigningKey::from_str)?;
+
+        match keys.len() {
+            1 => Ok(keys.remove(0)),
+            count => Err(PngError::InvalidArgument { reason: format!("expected one signing key, got {}", count) }),
+        }
+    }
+
+    /// The contents of a key file for this key, with the verifying key in a
+    /// comment.
+    pub fn to_file(&self) -> Zeroizing<String> {
+        key_file("verifying key", &self.verifying_key(), &self.to_secret_string())
+    }
+}
+
+impl VerifyingKey {
+    pub fn key_id(&self) -> KeyId {
+        let digest = Sha256::digest(self.0.as_bytes());
+
+        KeyId(digest[..KEY_ID_LENGTH].try_into().unwrap())
+    }
+}
+
+impl SignatureChunk {
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// The part of the chunk data that precedes the signature, which is
+    /// signed along with the image.
+    fn signed_fields(&self) -> Vec<u8> {
+        let mut fields = vec![VERSION];
+        fields.extend_from_slice(&self.key_id.0);
+        fields.push(self.chunk_types.len() as u8);
+        for chunk_type in &self.chunk_types {
+            fields.extend_from_slice(&chunk_type.bytes());
+        }
+
+        fields
+    }
+
+    /// SHA-256 over the signed fields and then the type, length and data of
+    /// every covered chunk, in file order.
+    fn digest(&self, png: &Png) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(DOMAIN);
+        hasher.update(self.signed_fields());
+
+        for chunk in png.chunks() {
+            if COVERED.contains(chunk.chunk_type()) || self.chunk_types.contains(chunk.chunk_type()) {
+                hasher.update(chunk.chunk_type().bytes());
+                hasher.update(chunk.length().to_be_bytes());
+                hasher.update(chunk.data());
+            }
+        }
+
+        hasher.finalize().into()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.signed_fields();
+        data.extend_from_slice(&self.signature.to_bytes());
+
+        Chunk::new(ChunkType::SIGNATURE, data)
+    }
+}
+
+impl TryFrom<&Chunk> for SignatureChunk {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let invalid = |reason: &str| PngError::InvalidChunkData {
+            chunk_type: ChunkType::SIGNATURE,
+            reason: reason.to_string(),
+        };
+
+        if *chunk.chunk_type() != ChunkType::SIGNATURE {
+            return Err(PngError::UnexpectedChunkType { expected: ChunkType::SIGNATURE, found: *chunk.chunk_type() });
+        }
+
+        let data = chunk.data();
+        if data.len() < 2 + KEY_ID_LENGTH {
+            return Err(invalid("too short"));
+        }
+        if data[0] != VERSION {
+            return Err(invalid(&format!("unsupported version {}", data[0])));
+        }
+
+        let count = data[1 + KEY_ID_LENGTH] as usize;
+        let types_end = 2 + KEY_ID_LENGTH + 4 * count;
+        if data.len() != types_end + Signature::BYTE_SIZE {
+            return Err(invalid(&format!("expected {} bytes, got {}", types_end + Signature::BYTE_SIZE, data.len())));
+        }
+
+        let chunk_types = data[2 + KEY_ID_LENGTH..types_end]
+            .chunks_exact(4)
+            .map(|bytes| ChunkType::try_from(<[u8; 4]>::try_from(bytes).unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            key_id: KeyId(data[1..1 + KEY_ID_LENGTH].try_into().unwrap()),
+            chunk_types,
+            signature: Signature::from_bytes(data[types_end..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Signs the image data of `png` and the chunks of the given ancillary
+/// `chunk_types`, replacing any earlier signature by the same key.
+///
+/// The signature chunk holds the version, the `KeyId`, the number of extra
+/// chunk types as one byte, the types themselves and then the 64-byte
+/// Ed25519 signature of a SHA-256 over those fields and the type, length and
+/// data of every covered chunk in file order.
+///
+/// Its type is ancillary and safe to copy, so editors that do not know it
+/// keep it even when they change the image, and `verify` then reports the
+/// change instead of the signature silently disappearing.
+pub fn sign(png: &mut Png, key: &SigningKey, chunk_types: &[ChunkType]) -> Result<()> {
+    for (index, chunk_type) in chunk_types.iter().enumerate() {
+        let reason = if chunk_type.is_critical() {
+            "critical chunks other than IHDR, PLTE and IDAT cannot be signed"
+        } else if *chunk_type == ChunkType::SIGNATURE {
+            "a signature cannot cover signatures"
+        } else if chunk_types[..index].contains(chunk_type) {
+            "listed more than once"
+        } else {
+            continue;
+        };
+
+        return Err(PngError::InvalidArgument { reason: format!("{}: {}", chunk_type, reason) });
+    }
+
+    if chunk_types.len() > u8::MAX as usize {
+        return Err(PngError::InvalidArgument { reason: format!("at most {} chunk types can be signed", u8::MAX) });
+    }
+
+    let key_id = key.verifying_key().key_id();
+    let kept = png
+        .chunks()
+        .iter()
+        .filter(|chunk| SignatureChunk::try_from(*chunk).map_or(true, |signature| signature.key_id != key_id))
+        .cloned()
+        .collect();
+    *png = Png::from_chunks(kept);
+
+    let mut signature = SignatureChunk {
+        key_id,
+        chunk_types: chunk_types.to_vec(),
+        signature: Signature::from_bytes(&[0; Signature::BYTE_SIZE]),
+    };
+    signature.signature = key.0.sign(&signature.digest(png));
+
+    png.insert_chunk(signature.to_chunk(), InsertPosition::BeforeEnd)
+}
+
+/// Checks the signatures in `png` against `keys` and returns the key of the
+/// first one that is valid. Fails if there is no signature, none was made
+/// by one of `keys`, or a covered chunk changed since signing. Signature
+/// chunks that cannot be parsed are skipped, and only reported if no other
+/// signature is valid.
+pub fn verify(png: &Png, keys: &[VerifyingKey]) -> Result<VerifyingKey> {
+    let mut signatures = Vec::new();
+    let mut malformed = Vec::new();
+    for chunk in png.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::SIGNATURE) {
+        match SignatureChunk::try_from(chunk) {
+            Ok(signature) => signatures.push(signature),
+            Err(e) => malformed.push(e),
+        }
+    }
+
+    if signatures.is_empty() && malformed.is_empty() {
+        return Err(PngError::ChunkNotFound { chunk_type: ChunkType::SIGNATURE });
+    }
+
+    let mut mismatch = None;
+    for signature in &signatures {
+        let Some(key) = keys.iter().find(|key| key.key_id() == signature.key_id) else {
+            continue;
+        };
+
+        match key.0.verify_strict(&signature.digest(png), &signature.signature) {
+            Ok(()) => return Ok(*key),
+            Err(_) => mismatch = Some(signature.key_id),
+        }
+    }
+
+    let mut reasons = Vec::new();
+    match mismatch {
+        Some(key_id) => reasons.push(format!("content changed since it was signed by key {}", key_id)),
+        None if !signatures.is_empty() => reasons.push(format!(
+            "signed by unknown key {}",
+            signatures.iter().map(|signature| signature.key_id.to_string()).collect::<Vec<_>>().join(", "),
+        )),
+        None => {},
+    }
+    reasons.extend(malformed.iter().map(|e| format!("malformed signature: {}", e)));
+
+    Err(PngError::Verification { reason: reasons.join("; ") })
+}
+
+impl Display for KeyId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl Debug for SigningKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SigningKey").field(&self.verifying_key()).finish()
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", VERIFYING_KEY_PREFIX, URL_SAFE_NO_PAD.encode(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ed25519_dalek::VerifyingKey::from_bytes(&decode_key(s, VERIFYING_KEY_PREFIX, "verifying")?)
+            .map(Self)
+            .map_err(|_| PngError::InvalidArgument { reason: String::from("invalid verifying key") })
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(decode_key(s, SIGNING_KEY_PREFIX, "signing")?);
+
+        Ok(Self(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("tEXt", b"Author\0pipeline"),
+            chunk("IDAT", &[0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+
+        sign(&mut png, &key, &[ChunkType::TEXT]).unwrap();
+
+        let signature = SignatureChunk::try_from(png.chunk_by_type("siGn").unwrap()).unwrap();
+        assert_eq!(signature.key_id, key.verifying_key().key_id());
+        assert_eq!(signature.chunk_types, vec![ChunkType::TEXT]);
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+
+        let bytes = png.as_bytes();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(verify(&png, &[key.verifying_key()]).unwrap(), key.verifying_key());
+    }
+
+    #[test]
+    fn test_verify_detects_changes() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+        sign(&mut png, &key, &[ChunkType::TEXT]).unwrap();
+
+        for index in [0, 1, 2] {
+            let mut chunks = png.chunks().to_vec();
+            let mut data = chunks[index].data().to_vec();
+            data[4] ^= 0x01;
+            chunks[index] = Chunk::new(*chunks[index].chunk_type(), data);
+
+            let result = verify(&Png::from_chunks(chunks), &[key.verifying_key()]);
+            assert!(matches!(result, Err(PngError::Verification { .. })), "chunk {}", index);
+        }
+
+        let mut without_text = Png::from_chunks(png.chunks().to_vec());
+        without_text.remove_chunk("tEXt").unwrap();
+        assert!(matches!(verify(&without_text, &[key.verifying_key()]), Err(PngError::Verification { .. })));
+
+        let mut with_comment = Png::from_chunks(png.chunks().to_vec());
+        with_comment.insert_chunk(chunk("zTXt", b"Note\0\0x\x9c\x03\x00\x00\x00\x00\x01"), InsertPosition::BeforeEnd).unwrap();
+        assert!(verify(&with_comment, &[key.verifying_key()]).is_ok());
+    }
+
+    #[test]
+    fn test_verify_keys() {
+        let first = SigningKey::generate();
+        let second = SigningKey::generate();
+        let mut png = testing_png();
+
+        assert!(matches!(verify(&png, &[first.verifying_key()]), Err(PngError::ChunkNotFound { .. })));
+
+        sign(&mut png, &first, &[]).unwrap();
+        sign(&mut png, &second, &[]).unwrap();
+        sign(&mut png, &first, &[]).unwrap();
+
+        assert_eq!(png.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::SIGNATURE).count(), 2);
+        assert_eq!(verify(&png, &[second.verifying_key()]).unwrap(), second.verifying_key());
+        assert!(matches!(
+            verify(&png, &[SigningKey::generate().verifying_key()]),
+            Err(PngError::Verification { reason }) if reason.contains("unknown key"),
+        ));
+    }
+
+    #[test]
+    fn test_verify_skips_malformed_signatures() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+        sign(&mut png, &key, &[]).unwrap();
+        png.insert_chunk(chunk("siGn", b"short"), InsertPosition::BeforeFirstIdat).unwrap();
+
+        assert_eq!(verify(&png, &[key.verifying_key()]).unwrap(), key.verifying_key());
+        assert!(matches!(
+            verify(&png, &[SigningKey::generate().verifying_key()]),
+            Err(PngError::Verification { reason }) if reason.contains("unknown key") && reason.contains("malformed"),
+        ));
+
+        let mut only_malformed = Png::from_chunks(png.chunks().to_vec());
+        only_malformed.remove_chunk("siGn").unwrap();
+        only_malformed.remove_chunk("siGn").unwrap();
+        only_malformed.insert_chunk(chunk("siGn", b"short"), InsertPosition::BeforeEnd).unwrap();
+        assert!(matches!(
+            verify(&only_malformed, &[key.verifying_key()]),
+            Err(PngError::Verification { reason }) if reason.starts_with("malformed signature: "),
+        ));
+    }
+
+    #[test]
+    fn test_sign_invalid_chunk_types() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+
+        for chunk_types in [vec![ChunkType::IEND], vec![ChunkType::SIGNATURE], vec![ChunkType::TEXT, ChunkType::TEXT]] {
+            assert!(matches!(sign(&mut png, &key, &chunk_types), Err(PngError::InvalidArgument { .. })));
+        }
+    }
+
+    #[test]
+    fn test_signature_chunk_type() {
+        assert!(!ChunkType::SIGNATURE.is_critical());
+        assert!(!ChunkType::SIGNATURE.is_public());
+        assert!(ChunkType::SIGNATURE.is_reserved_bit_valid());
+        assert!(ChunkType::SIGNATURE.is_safe_to_copy());
+    }
+
+    #[test]
+    fn test_key_strings() {
+        let key = SigningKey::generate();
+        let verifying_key = key.verifying_key();
+
+        assert_eq!(VerifyingKey::from_str(&verifying_key.to_string()).unwrap(), verifying_key);
+        assert_eq!(SigningKey::parse_file(&key.to_file()).unwrap().verifying_key(), verifying_key);
+        assert!(!format!("{:?}", key).contains(SIGNING_KEY_PREFIX));
+        assert_eq!(verifying_key.key_id().to_string().len(), 16);
+        assert!(SigningKey::parse_file("").is_err());
+        assert!(matches!(
+            SigningKey::parse_file("# comment\n\nnot a key\n"),
+            Err(PngError::InvalidArgument { reason }) if reason.starts_with("line 3: ")
+        ));
+    }
+}
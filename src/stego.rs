@@ -0,0 +1,420 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{PngError, Result};
+use crate::checksum;
+use crate::chunk_type::ChunkType;
+use crate::encoder::Encoder;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::image::Image;
+use crate::png::Png;
+use crate::recipient::KEY_LENGTH;
+use crate::secret::{derive_key, KdfParams};
+
+/// Bytes in front of the message: its length and its CRC-32, both
+/// big-endian.
+pub const HEADER_BYTES: usize = 8;
+
+/// Argon2id salt for the seed both streams are drawn from. Fixed, so the
+/// same key finds the message again.
+const SEED_SALT: &[u8] = b"png-rs lsb seed v1";
+const ORDER_DOMAIN: &[u8] = b"png-rs lsb order v1\0";
+const WHITENING_DOMAIN: &[u8] = b"png-rs lsb whitening v1\0";
+
+/// The image with a message hidden in it, returned by `embed`.
+pub struct Embedded {
+    pub png: Png,
+    /// How many message bytes the image holds in total, as `capacity`.
+    pub capacity: usize,
+}
+
+/// How many message bytes `embed` can hide in `png` with the given number
+/// of bits per channel, after the header.
+pub fn capacity(png: &Png, bits_per_channel: u8) -> Result<usize> {
+    let ihdr = png.ihdr()?;
+    check_format(png, &ihdr, bits_per_channel)?;
+
+    Ok(message_capacity(&ihdr, bits_per_channel))
+}
+
+/// Hides `message` in the lowest `bits_per_channel` bits of the color
+/// samples of `png` and re-encodes the image.
+///
+/// The samples used and their order come from a ChaCha20 stream seeded from
+/// `key` with Argon2id, and the header and message are XORed with a second
+/// such stream so that the changed bits look like noise. Alpha samples are
+/// never touched. This hides the message but does not protect it; encrypt it
+/// first for that. The result has the chunks of the input, with `IHDR` and
+/// the `IDAT` chunks of the new encoding in place of the old ones.
+pub fn embed(png: &Png, message: &[u8], key: &[u8], bits_per_channel: u8) -> Result<Embedded> {
+    let image = png.decode()?;
+    let ihdr = *image.ihdr();
+    check_format(png, &ihdr, bits_per_channel)?;
+
+    let length = u32::try_from(message.len())
+        .map_err(|_| PngError::InvalidArgument { reason: String::from("message is too long") })?;
+    let capacity = message_capacity(&ihdr, bits_per_channel);
+    if message.len() > capacity {
+        return Err(PngError::InvalidArgument {
+            reason: format!("message is {} bytes but the image holds at most {}", message.len(), capacity),
+        });
+    }
+
+    let seed = seed(key)?;
+    let mut whitening = stream(WHITENING_DOMAIN, &seed);
+    let mut payload = [length.to_be_bytes(), checksum::crc32(message).to_be_bytes()].concat();
+    payload.extend_from_slice(message);
+    let (header, body) = payload.split_at_mut(HEADER_BYTES);
+    whiten(&mut whitening, header);
+    whiten(&mut whitening, body);
+
+    let mut samples = image.samples();
+    let mut slots = Slots::new(eligible(&ihdr), &seed);
+    let mask = (1u16 << bits_per_channel) - 1;
+
+    for group in groups(&payload, bits_per_channel) {
+        let index = slots.next().expect("capacity was checked");
+        samples[index] = (samples[index] & !mask) | group;
+    }
+
+    let encoded = Encoder::from_ihdr(ihdr)?.encode(Image::from_samples(ihdr, &samples)?.data())?;
+
+    Ok(Embedded { png: merge(png, &encoded), capacity })
+}
+
+/// Recovers a message hidden by `embed` with the same `key` and
+/// `bits_per_channel`. Fails with `PngError::MessageNotFound` if there is
+/// none, or the key or number of bits is wrong.
+pub fn extract(png: &Png, key: &[u8], bits_per_channel: u8) -> Result<Vec<u8>> {
+    let image = png.decode()?;
+    let ihdr = *image.ihdr();
+    check_format(png, &ihdr, bits_per_channel)?;
+
+    let capacity = message_capacity(&ihdr, bits_per_channel);
+    if capacity == 0 {
+        return Err(not_found("image is too small to hold a message"));
+    }
+
+    let samples = image.samples();
+    let seed = seed(key)?;
+    let mut slots = Slots::new(eligible(&ihdr), &seed);
+    let mut whitening = stream(WHITENING_DOMAIN, &seed);
+
+    let mut bits = Bits::new(bits_per_channel);
+    let mut read = |count: usize| -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(count);
+        while bytes.len() < count {
+            let index = slots.next().expect("length was checked against the capacity");
+            bytes.extend(bits.push(samples[index]));
+        }
+        bytes
+    };
+
+    let mut header = read(HEADER_BYTES);
+    whiten(&mut whitening, &mut header);
+    let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let crc = u32::from_be_bytes(header[4..].try_into().unwrap());
+
+    if length > capacity {
+        return Err(not_found("wrong key or number of bits, or no message"));
+    }
+
+    let mut message = read(length);
+    whiten(&mut whitening, &mut message);
+
+    if checksum::crc32(&message) != crc {
+        return Err(not_found("checksum mismatch: wrong key or number of bits, or no message"));
+    }
+
+    Ok(message)
+}
+
+fn check_format(png: &Png, ihdr: &Ihdr, bits_per_channel: u8) -> Result<()> {
+    if ihdr.color_type == ColorType::Indexed {
+        return Err(PngError::Unsupported { reason: String::from("changing palette indices changes colors") });
+    }
+    if ihdr.bit_depth < 8 {
+        return Err(PngError::Unsupported { reason: format!("{}-bit samples are too coarse", ihdr.bit_depth) });
+    }
+    if png.chunk_by_type(&ChunkType::TRNS.to_string()).is_some() {
+        return Err(PngError::Unsupported { reason: String::from("changed pixels could turn transparent") });
+    }
+    if bits_per_channel == 0 || bits_per_channel > ihdr.bit_depth / 2 {
+        return Err(PngError::InvalidArgument {
+            reason: format!("bits per channel must be 1 to {} for {}-bit samples", ihdr.bit_depth / 2, ihdr.bit_depth),
+        });
+    }
+
+    Ok(())
+}
+
+/// Message bytes that fit in the color samples of an `ihdr` image, after
+/// the header.
+fn message_capacity(ihdr: &Ihdr, bits_per_channel: u8) -> usize {
+    let pixels = ihdr.width as usize * ihdr.height as usize;
+
+    (pixels * color_channels(ihdr) * bits_per_channel as usize / 8).saturating_sub(HEADER_BYTES)
+}
+
+/// Indices, into `Image::samples`, of every color sample.
+fn eligible(ihdr: &Ihdr) -> Vec<usize> {
+    let channels = ihdr.color_type.channels();
+    let color_channels = color_channels(ihdr);
+    let pixels = ihdr.width as usize * ihdr.height as usize;
+
+    let mut indices = Vec::with_capacity(pixels * color_channels);
+    for pixel in 0..pixels {
+        indices.extend(pixel * channels..pixel * channels + color_channels);
+    }
+    indices
+}
+
+/// Channels per pixel other than alpha.
+fn color_channels(ihdr: &Ihdr) -> usize {
+    match ihdr.color_type {
+        ColorType::GrayscaleAlpha | ColorType::Rgba => ihdr.color_type.channels() - 1,
+        _ => ihdr.color_type.channels(),
+    }
+}
+
+/// Stretches `key` with Argon2id so that guessing it costs as much as
+/// guessing a passphrase.
+fn seed(key: &[u8]) -> Result<Zeroizing<[u8; KEY_LENGTH]>> {
+    derive_key(key, SEED_SALT, KdfParams::default())
+}
+
+fn stream(domain: &[u8], seed: &[u8; KEY_LENGTH]) -> ChaCha20Rng {
+    ChaCha20Rng::from_seed(Sha256::new().chain_update(domain).chain_update(seed).finalize().into())
+}
+
+fn whiten(stream: &mut ChaCha20Rng, bytes: &mut [u8]) {
+    let mut keystream = vec![0; bytes.len()];
+    stream.fill_bytes(&mut keystream);
+
+    bytes.iter_mut().zip(keystream).for_each(|(byte, key)| *byte ^= key);
+}
+
+/// Splits `bytes` into groups of `bits` bits, most significant first. The
+/// last group is padded with zeros.
+fn groups(bytes: &[u8], bits: u8) -> impl Iterator<Item = u16> + '_ {
+    let total = bytes.len() * 8;
+
+    (0..total.div_ceil(bits as usize)).map(move |group| {
+        (0..bits as usize).fold(0, |value, offset| {
+            let bit = group * bits as usize + offset;
+            let set = bit < total && bytes[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            (value << 1) | set as u16
+        })
+    })
+}
+
+/// Reassembles bytes from the low bits of samples, the inverse of `groups`.
+struct Bits {
+    bits: u8,
+    value: u32,
+    count: u8,
+}
+
+impl Bits {
+    fn new(bits: u8) -> Self {
+        Self { bits, value: 0, count: 0 }
+    }
+
+    /// Adds the low bits of `sample` and returns the byte completed by them,
+    /// if any.
+    fn push(&mut self, sample: u16) -> Option<u8> {
+        self.value = (self.value << self.bits) | (sample & ((1 << self.bits) - 1)) as u32;
+        self.count += self.bits;
+
+        (self.count >= 8).then(|| {
+            self.count -= 8;
+            let byte = (self.value >> self.count) as u8;
+            self.value &= (1 << self.count) - 1;
+            byte
+        })
+    }
+}
+
+/// The sample indices in key-seeded random order: a Fisher-Yates shuffle
+/// done lazily, so only as many slots as are used get shuffled.
+struct Slots {
+    indices: Vec<usize>,
+    next: usize,
+    rng: ChaCha20Rng,
+}
+
+impl Slots {
+    fn new(indices: Vec<usize>, seed: &[u8; KEY_LENGTH]) -> Self {
+        Self { indices, next: 0, rng: stream(ORDER_DOMAIN, seed) }
+    }
+
+    /// A uniform value below `bound`, by rejection sampling so that the
+    /// order does not depend on the `rand` version.
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+
+        loop {
+            let value = self.rng.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+impl Iterator for Slots {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next == self.indices.len() {
+            return None;
+        }
+
+        let swap = self.next + self.below((self.indices.len() - self.next) as u64) as usize;
+        self.indices.swap(self.next, swap);
+        self.next += 1;
+
+        Some(self.indices[self.next - 1])
+    }
+}
+
+/// `original` with its `IHDR` and `IDAT` chunks replaced by those of
+/// `encoded`.
+fn merge(original: &Png, encoded: &Png) -> Png {
+    let mut chunks = Vec::new();
+    let mut idat_written = false;
+
+    for chunk in original.chunks() {
+        match *chunk.chunk_type() {
+            ChunkType::IHDR => chunks.push(encoded.chunks()[0].clone()),
+            ChunkType::IDAT if idat_written => {},
+            ChunkType::IDAT => {
+                chunks.extend(encoded.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT).cloned());
+                idat_written = true;
+            },
+            _ => chunks.push(chunk.clone()),
+        }
+    }
+
+    Png::from_chunks(chunks)
+}
+
+fn not_found(reason: &str) -> PngError {
+    PngError::MessageNotFound { reason: String::from(reason) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::chunk::Chunk;
+    use crate::ordering::InsertPosition;
+
+    fn testing_png(color_type: ColorType, bit_depth: u8) -> Png {
+        let encoder = Encoder::new(40, 30, color_type, bit_depth).unwrap();
+        let length = encoder.ihdr().row_bytes(40) * 30;
+        let data: Vec<u8> = (0..length).map(|i| (i * 7 % 256) as u8).collect();
+
+        let mut png = encoder.encode(&data).unwrap();
+        png.insert_chunk(Chunk::new(ChunkType::TEXT, b"Title\0holiday".to_vec()), InsertPosition::BeforeFirstIdat)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        for (color_type, bit_depth, bits) in [(ColorType::Rgb, 8, 1), (ColorType::Rgba, 8, 3), (ColorType::Grayscale, 16, 8)] {
+            let png = testing_png(color_type, bit_depth);
+            let message = b"the quick brown fox jumps over the lazy dog";
+
+            let stego = embed(&png, message, b"key", bits).unwrap().png;
+            let stego = Png::try_from(stego.as_bytes().as_ref()).unwrap();
+
+            assert_eq!(extract(&stego, b"key", bits).unwrap(), message, "{} {}", color_type, bits);
+        }
+    }
+
+    #[test]
+    fn test_embed_changes_only_low_bits() {
+        let png = testing_png(ColorType::Rgba, 8);
+        let stego = embed(&png, b"hidden", b"key", 2).unwrap().png;
+
+        let types = |png: &Png| png.chunks().iter().map(|chunk| *chunk.chunk_type()).collect::<Vec<_>>();
+        assert_eq!(types(&stego), types(&png));
+
+        let before = png.decode().unwrap().samples();
+        let after = stego.decode().unwrap().samples();
+        let changed = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+
+        assert!(changed > 0);
+        for (index, (a, b)) in before.iter().zip(&after).enumerate() {
+            assert_eq!(a & !0b11, b & !0b11);
+            if index % 4 == 3 {
+                assert_eq!(a, b, "alpha sample {} changed", index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_wrong_key_or_bits() {
+        let png = testing_png(ColorType::Rgb, 8);
+        let stego = embed(&png, b"hidden", b"key", 2).unwrap().png;
+
+        assert!(matches!(extract(&stego, b"other key", 2), Err(PngError::MessageNotFound { .. })));
+        assert!(matches!(extract(&stego, b"key", 1), Err(PngError::MessageNotFound { .. })));
+        assert!(matches!(extract(&png, b"key", 2), Err(PngError::MessageNotFound { .. })));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(ColorType::Rgb, 8);
+        let capacity = capacity(&png, 1).unwrap();
+
+        assert_eq!(capacity, 40 * 30 * 3 / 8 - HEADER_BYTES);
+        assert_eq!(embed(&png, &vec![b'x'; capacity], b"key", 1).unwrap().capacity, capacity);
+        assert!(matches!(embed(&png, &vec![b'x'; capacity + 1], b"key", 1), Err(PngError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_order_depends_only_on_key() {
+        let ihdr = *testing_png(ColorType::Rgb, 8).decode().unwrap().ihdr();
+        let order = |key: &[u8]| Slots::new(eligible(&ihdr), &seed(key).unwrap()).take(64).collect::<Vec<_>>();
+
+        assert_eq!(order(b"key"), order(b"key"));
+        assert_ne!(order(b"key"), order(b"other key"));
+    }
+
+    #[test]
+    fn test_unsupported_formats() {
+        let png = testing_png(ColorType::Rgb, 8);
+        assert!(matches!(embed(&png, b"x", b"key", 0), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(embed(&png, b"x", b"key", 5), Err(PngError::InvalidArgument { .. })));
+
+        let png = testing_png(ColorType::Grayscale, 4);
+        assert!(matches!(embed(&png, b"x", b"key", 1), Err(PngError::Unsupported { .. })));
+
+        let mut png = testing_png(ColorType::Rgb, 8);
+        png.insert_chunk(Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0; 6]), InsertPosition::BeforeFirstIdat)
+            .unwrap();
+        assert!(matches!(embed(&png, b"x", b"key", 1), Err(PngError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn test_groups_and_bits() {
+        for bits in 1..=8 {
+            let bytes = [0xa5, 0x3c, 0xff];
+            let mut reader = Bits::new(bits);
+            let mut groups = groups(&bytes, bits);
+            let mut recovered = Vec::new();
+
+            while recovered.len() < bytes.len() {
+                recovered.extend(reader.push(groups.next().unwrap()));
+            }
+
+            assert_eq!(recovered, bytes, "{} bits", bits);
+        }
+    }
+}
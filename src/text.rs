@@ -0,0 +1,359 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// A textual metadata chunk (PNG spec, 11.3.4).
+///
+/// `tEXt` and `zTXt` hold Latin-1 text, `iTXt` holds UTF-8. Keywords are
+/// Latin-1 in all three.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    /// `tEXt`: uncompressed Latin-1 text.
+    Text { keyword: String, text: String },
+    /// `zTXt`: zlib-compressed Latin-1 text.
+    Compressed { keyword: String, text: String },
+    /// `iTXt`: UTF-8 text, optionally compressed, with a language tag and
+    /// the keyword translated into that language.
+    International {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String,
+    },
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let chunk_type = *chunk.chunk_type();
+        let invalid = |reason: String| PngError::InvalidChunkData { chunk_type, reason };
+
+        if !matches!(chunk_type, ChunkType::TEXT | ChunkType::ZTXT | ChunkType::ITXT) {
+            return Err(PngError::UnexpectedChunkType { expected: ChunkType::TEXT, found: chunk_type });
+        }
+
+        let (keyword, rest) = split_nul(chunk.data()).ok_or_else(|| invalid(String::from("no keyword separator")))?;
+        let keyword = latin1(keyword);
+        check_keyword(&keyword).map_err(invalid)?;
+
+        let text_chunk = match chunk_type {
+            ChunkType::TEXT => TextChunk::Text { keyword, text: latin1(rest) },
+            ChunkType::ZTXT => match rest.split_first() {
+                Some((0, compressed)) => TextChunk::Compressed {
+                    keyword,
+                    text: latin1(&inflate(compressed).map_err(invalid)?),
+                },
+                Some((method, _)) => return Err(invalid(format!("unknown compression method {}", method))),
+                None => return Err(invalid(String::from("missing compression method"))),
+            },
+            ChunkType::ITXT => {
+                let [flag, method, rest @ ..] = rest else {
+                    return Err(invalid(String::from("missing compression flag")));
+                };
+                let compressed = match (flag, method) {
+                    (0, 0) => false,
+                    (1, 0) => true,
+                    (0 | 1, method) => return Err(invalid(format!("unknown compression method {}", method))),
+                    (flag, _) => return Err(invalid(format!("invalid compression flag {}", flag))),
+                };
+
+                let (language_tag, rest) =
+                    split_nul(rest).ok_or_else(|| invalid(String::from("no language tag separator")))?;
+                let (translated_keyword, text) =
+                    split_nul(rest).ok_or_else(|| invalid(String::from("no translated keyword separator")))?;
+                let text = match compressed {
+                    true => inflate(text).map_err(invalid)?,
+                    false => text.to_vec(),
+                };
+
+                let language_tag = String::from_utf8(language_tag.to_vec())
+                    .map_err(|_| invalid(String::from("language tag is not ASCII")))?;
+                check_language_tag(&language_tag).map_err(invalid)?;
+
+                TextChunk::International {
+                    keyword,
+                    compressed,
+                    language_tag,
+                    translated_keyword: String::from_utf8(translated_keyword.to_vec())
+                        .map_err(|_| invalid(String::from("translated keyword is not UTF-8")))?,
+                    text: String::from_utf8(text).map_err(|_| invalid(String::from("text is not UTF-8")))?,
+                }
+            },
+            _ => unreachable!(),
+        };
+
+        Ok(text_chunk)
+    }
+}
+
+impl TextChunk {
+    /// Largest decompressed text accepted from `zTXt` and `iTXt` chunks.
+    pub const MAX_TEXT_LENGTH: usize = 1 << 24;
+    pub const MAX_KEYWORD_LENGTH: usize = 79;
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self> {
+        let chunk = TextChunk::Text { keyword: keyword.to_string(), text: text.to_string() };
+        chunk.validate()?;
+
+        Ok(chunk)
+    }
+
+    pub fn new_compressed(keyword: &str, text: &str) -> Result<Self> {
+        let chunk = TextChunk::Compressed { keyword: keyword.to_string(), text: text.to_string() };
+        chunk.validate()?;
+
+        Ok(chunk)
+    }
+
+    pub fn new_international(
+        keyword: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        text: &str,
+        compressed: bool,
+    ) -> Result<Self> {
+        let chunk = TextChunk::International {
+            keyword: keyword.to_string(),
+            compressed,
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string(),
+        };
+        chunk.validate()?;
+
+        Ok(chunk)
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        match self {
+            TextChunk::Text { .. } => ChunkType::TEXT,
+            TextChunk::Compressed { .. } => ChunkType::ZTXT,
+            TextChunk::International { .. } => ChunkType::ITXT,
+        }
+    }
+
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::Compressed { keyword, .. }
+            | TextChunk::International { keyword, .. } => keyword,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::Compressed { text, .. }
+            | TextChunk::International { text, .. } => text,
+        }
+    }
+
+    /// Checks the keyword, that Latin-1 text can be encoded as such and
+    /// that no field contains a NUL separator.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason| PngError::InvalidArgument { reason };
+
+        validate_keyword(self.keyword())?;
+
+        match self {
+            TextChunk::Text { text, .. } | TextChunk::Compressed { text, .. } => match to_latin1(text) {
+                None => Err(invalid(String::from("text is not Latin-1"))),
+                Some(bytes) if bytes.contains(&0) => Err(invalid(String::from("text contains a NUL"))),
+                Some(_) => Ok(()),
+            },
+            TextChunk::International { language_tag, translated_keyword, .. } => {
+                check_language_tag(language_tag).map_err(invalid)?;
+                match translated_keyword.contains('\0') {
+                    true => Err(invalid(String::from("translated keyword contains a NUL"))),
+                    false => Ok(()),
+                }
+            },
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        self.validate()?;
+
+        let mut data = to_latin1(self.keyword()).unwrap_or_default();
+        data.push(0);
+
+        match self {
+            TextChunk::Text { text, .. } => data.extend(to_latin1(text).unwrap_or_default()),
+            TextChunk::Compressed { text, .. } => {
+                data.push(0);
+                data.extend(deflate(&to_latin1(text).unwrap_or_default())?);
+            },
+            TextChunk::International { compressed, language_tag, translated_keyword, text, .. } => {
+                data.extend([*compressed as u8, 0]);
+                data.extend(language_tag.as_bytes());
+                data.push(0);
+                data.extend(translated_keyword.as_bytes());
+                data.push(0);
+                match compressed {
+                    true => data.extend(deflate(text.as_bytes())?),
+                    false => data.extend(text.as_bytes()),
+                }
+            },
+        }
+
+        Ok(Chunk::new(self.chunk_type(), data))
+    }
+}
+
+/// Checks that `keyword` is 1 to 79 printable Latin-1 characters without
+/// leading, trailing or consecutive spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    check_keyword(keyword).map_err(|reason| PngError::InvalidArgument { reason })
+}
+
+fn check_keyword(keyword: &str) -> std::result::Result<(), String> {
+    let length = keyword.chars().count();
+
+    if length == 0 || length > TextChunk::MAX_KEYWORD_LENGTH {
+        return Err(format!("keyword length {} is not 1 to {}", length, TextChunk::MAX_KEYWORD_LENGTH));
+    }
+
+    if let Some(c) = keyword.chars().find(|&c| !matches!(c as u32, 32..=126 | 161..=255)) {
+        return Err(format!("keyword contains {:?}", c));
+    }
+
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(format!("keyword {:?} has leading, trailing or consecutive spaces", keyword));
+    }
+
+    Ok(())
+}
+
+/// Language tags are hyphen-separated words of ASCII letters and digits.
+fn check_language_tag(tag: &str) -> std::result::Result<(), String> {
+    match tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        true => Ok(()),
+        false => Err(format!("invalid language tag {:?}", tag)),
+    }
+}
+
+fn split_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = data.iter().position(|&b| b == 0)?;
+
+    Some((&data[..index], &data[index + 1..]))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn to_latin1(string: &str) -> Option<Vec<u8>> {
+    string.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Inflates text, refusing anything above `TextChunk::MAX_TEXT_LENGTH`.
+fn inflate(compressed: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut text = Vec::new();
+
+    ZlibDecoder::new(compressed)
+        .take(TextChunk::MAX_TEXT_LENGTH as u64 + 1)
+        .read_to_end(&mut text)
+        .map_err(|e| format!("cannot decompress text: {}", e))?;
+
+    if text.len() > TextChunk::MAX_TEXT_LENGTH {
+        return Err(format!("decompressed text is longer than {} bytes", TextChunk::MAX_TEXT_LENGTH));
+    }
+
+    Ok(text)
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data).map_err(PngError::Zlib)?;
+
+    encoder.finish().map_err(PngError::Zlib)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(chunk: &TextChunk) -> TextChunk {
+        let bytes = chunk.to_chunk().unwrap().as_bytes();
+
+        TextChunk::try_from(&Chunk::try_from(bytes.as_ref()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_text_latin1() {
+        let chunk = TextChunk::new("Author", "Zoë").unwrap();
+
+        assert_eq!(chunk.to_chunk().unwrap().data(), b"Author\0Zo\xeb");
+        assert_eq!(round_trip(&chunk), chunk);
+    }
+
+    #[test]
+    fn test_text_not_latin1() {
+        assert!(matches!(TextChunk::new("Title", "日本"), Err(PngError::InvalidArgument { .. })));
+        assert!(matches!(TextChunk::new("Title", "a\0b"), Err(PngError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_compressed_text() {
+        let chunk = TextChunk::new_compressed("Comment", &"long text ".repeat(100)).unwrap();
+        let encoded = chunk.to_chunk().unwrap();
+
+        assert_eq!(*encoded.chunk_type(), ChunkType::ZTXT);
+        assert!(encoded.data().len() < 100);
+        assert_eq!(round_trip(&chunk), chunk);
+    }
+
+    #[test]
+    fn test_international_text() {
+        for compressed in [false, true] {
+            let chunk = TextChunk::new_international("Title", "ja-JP", "タイトル", "日本語のテキスト", compressed).unwrap();
+
+            assert_eq!(round_trip(&chunk), chunk);
+            assert_eq!(chunk.text(), "日本語のテキスト");
+        }
+    }
+
+    #[test]
+    fn test_validate_keyword() {
+        assert!(validate_keyword("Author").is_ok());
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+
+        for keyword in ["", " Author", "Author ", "Creation  Time", "Tab\tbed", "日本", &"k".repeat(80)] {
+            assert!(validate_keyword(keyword).is_err(), "{:?}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_invalid_text_chunks() {
+        let cases = [
+            Chunk::new(ChunkType::TEXT, b"no separator".to_vec()),
+            Chunk::new(ChunkType::TEXT, b" bad\0text".to_vec()),
+            Chunk::new(ChunkType::ZTXT, b"Comment\0\x01data".to_vec()),
+            Chunk::new(ChunkType::ZTXT, b"Comment\0\0not zlib".to_vec()),
+            Chunk::new(ChunkType::ITXT, b"Title\0\x02\0\0\0text".to_vec()),
+            Chunk::new(ChunkType::ITXT, b"Title\0\0\x01\0\0text".to_vec()),
+            Chunk::new(ChunkType::ITXT, b"Title\0\0\0en\0".to_vec()),
+            Chunk::new(ChunkType::ITXT, b"Title\0\0\0en\0\0\xff".to_vec()),
+        ];
+
+        for chunk in &cases {
+            assert!(
+                matches!(TextChunk::try_from(chunk), Err(PngError::InvalidChunkData { .. })),
+                "{:?}",
+                chunk.data(),
+            );
+        }
+
+        let ihdr = Chunk::new(ChunkType::IHDR, Vec::new());
+        assert!(matches!(TextChunk::try_from(&ihdr), Err(PngError::UnexpectedChunkType { .. })));
+    }
+}
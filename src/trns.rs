@@ -0,0 +1,131 @@
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::plte::Plte;
+
+/// Simple transparency (PNG spec, 11.3.2.1). Its layout depends on the
+/// color type, so it is parsed against the image header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trns {
+    /// Pixels with this gray sample are fully transparent.
+    Gray(u16),
+    /// Pixels with exactly these red, green and blue samples are fully
+    /// transparent.
+    Rgb([u16; 3]),
+    /// Alpha for the first palette entries. Entries past the end are
+    /// opaque.
+    Indexed(Vec<u8>),
+}
+
+impl Trns {
+    pub fn from_chunk(chunk: &Chunk, ihdr: &Ihdr) -> Result<Self> {
+        if *chunk.chunk_type() != ChunkType::TRNS {
+            return Err(PngError::UnexpectedChunkType {
+                expected: ChunkType::TRNS,
+                found: *chunk.chunk_type(),
+            });
+        }
+
+        let data = chunk.data();
+        let sample = |i: usize| u16::from_be_bytes([data[2 * i], data[2 * i + 1]]);
+
+        let trns = match (ihdr.color_type, data.len()) {
+            (ColorType::Grayscale, 2) => Trns::Gray(sample(0)),
+            (ColorType::Rgb, 6) => Trns::Rgb([sample(0), sample(1), sample(2)]),
+            (ColorType::Indexed, _) => Trns::Indexed(data.to_vec()),
+            (ColorType::GrayscaleAlpha | ColorType::Rgba, _) => {
+                return Err(invalid(format!("not allowed for {} images", ihdr.color_type)));
+            },
+            (_, length) => return Err(invalid(format!("length {} is wrong for {} images", length, ihdr.color_type))),
+        };
+
+        trns.validate(ihdr, None)?;
+
+        Ok(trns)
+    }
+
+    /// Checks that this chunk fits the image: the variant must match the
+    /// color type, keys must fit the bit depth and palette alpha may not
+    /// have more entries than `plte`, if given.
+    pub fn validate(&self, ihdr: &Ihdr, plte: Option<&Plte>) -> Result<()> {
+        let max_sample = ((1u32 << ihdr.bit_depth) - 1) as u16;
+
+        match (self, ihdr.color_type) {
+            (Trns::Gray(gray), ColorType::Grayscale) if *gray > max_sample => {
+                Err(invalid(format!("gray key {} does not fit a bit depth of {}", gray, ihdr.bit_depth)))
+            },
+            (Trns::Rgb(rgb), ColorType::Rgb) if rgb.iter().any(|&sample| sample > max_sample) => {
+                Err(invalid(format!("color key {:?} does not fit a bit depth of {}", rgb, ihdr.bit_depth)))
+            },
+            (Trns::Indexed(alpha), ColorType::Indexed) => match plte {
+                _ if alpha.len() > Plte::MAX_ENTRIES => Err(invalid(format!("{} alpha entries", alpha.len()))),
+                Some(plte) if alpha.len() > plte.len() => Err(invalid(format!(
+                    "{} alpha entries for a palette of {}",
+                    alpha.len(),
+                    plte.len(),
+                ))),
+                _ => Ok(()),
+            },
+            (Trns::Gray(_), ColorType::Grayscale) | (Trns::Rgb(_), ColorType::Rgb) => Ok(()),
+            _ => Err(invalid(format!("does not match {} images", ihdr.color_type))),
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            Trns::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Trns::Rgb(rgb) => rgb.iter().flat_map(|sample| sample.to_be_bytes()).collect(),
+            Trns::Indexed(alpha) => alpha.clone(),
+        };
+
+        Chunk::new(ChunkType::TRNS, data)
+    }
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidChunkData { chunk_type: ChunkType::TRNS, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trns_gray() {
+        let ihdr = Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap();
+        let chunk = Chunk::new(ChunkType::TRNS, vec![0, 9]);
+        let trns = Trns::from_chunk(&chunk, &ihdr).unwrap();
+
+        assert_eq!(trns, Trns::Gray(9));
+        assert_eq!(trns.to_chunk().as_bytes(), chunk.as_bytes());
+        assert!(Trns::from_chunk(&Chunk::new(ChunkType::TRNS, vec![0, 16]), &ihdr).is_err());
+    }
+
+    #[test]
+    fn test_trns_rgb() {
+        let ihdr = Ihdr::new(1, 1, 16, ColorType::Rgb).unwrap();
+        let chunk = Chunk::new(ChunkType::TRNS, vec![1, 0, 2, 0, 3, 0]);
+
+        assert_eq!(Trns::from_chunk(&chunk, &ihdr).unwrap(), Trns::Rgb([256, 512, 768]));
+        assert!(Trns::from_chunk(&Chunk::new(ChunkType::TRNS, vec![1, 0]), &ihdr).is_err());
+    }
+
+    #[test]
+    fn test_trns_indexed() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap();
+        let plte = Plte::new(vec![[0; 3]; 2]).unwrap();
+        let trns = Trns::from_chunk(&Chunk::new(ChunkType::TRNS, vec![0, 128, 255]), &ihdr).unwrap();
+
+        assert_eq!(trns, Trns::Indexed(vec![0, 128, 255]));
+        assert!(trns.validate(&ihdr, Some(&plte)).is_err());
+    }
+
+    #[test]
+    fn test_trns_not_allowed_with_alpha() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Rgba).unwrap();
+
+        assert!(Trns::from_chunk(&Chunk::new(ChunkType::TRNS, vec![0, 0]), &ihdr).is_err());
+        assert!(Trns::Gray(0).validate(&ihdr, None).is_err());
+    }
+}
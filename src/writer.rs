@@ -0,0 +1,129 @@
+use std::io::Write;
+
+use crate::{PngError, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Writes a PNG one chunk at a time.
+///
+/// The signature is written before the first chunk. Chunks are serialized as
+/// soon as they are pushed, and `finish` closes the stream with an `IEND`
+/// chunk unless one was already written.
+#[derive(Debug)]
+pub struct ChunkWriter<W: Write> {
+    inner: W,
+    signature_written: bool,
+    ended: bool,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            signature_written: false,
+            ended: false,
+        }
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.write_data(chunk.chunk_type(), chunk.data())
+    }
+
+    /// Writes a chunk from its type and data, computing the length and CRC
+    /// without building an intermediate `Chunk`.
+    pub fn write_data(&mut self, chunk_type: &ChunkType, data: &[u8]) -> Result<()> {
+        if self.ended {
+            return Err(PngError::ChunkAfterEnd { chunk_type: *chunk_type });
+        }
+
+        let length = match u32::try_from(data.len()) {
+            Ok(length) if length <= Chunk::MAX_LENGTH => length,
+            _ => return Err(PngError::InvalidLength { length: data.len() as u64, offset: None }),
+        };
+
+        self.write_signature()?;
+
+        self.inner.write_all(&length.to_be_bytes())?;
+        self.inner.write_all(&chunk_type.bytes())?;
+        self.inner.write_all(data)?;
+        self.inner.write_all(&Chunk::calculate_crc(chunk_type, data).to_be_bytes())?;
+
+        if *chunk_type == ChunkType::IEND {
+            self.ended = true;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `IEND` if it has not been written yet, flushes the stream and
+    /// returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.ended {
+            self.write_data(&ChunkType::IEND, &[])?;
+        }
+
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_signature(&mut self) -> Result<()> {
+        if !self.signature_written {
+            self.inner.write_all(&Png::STANDARD_HEADER)?;
+            self.signature_written = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_chunk() -> Chunk {
+        Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"This is where your secret message will be!".to_vec())
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_chunk(&testing_chunk()).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks()[0].crc(), 2882656334);
+        assert_eq!(png.chunks()[1].chunk_type(), &ChunkType::IEND);
+    }
+
+    #[test]
+    fn test_finish_empty() {
+        let bytes = ChunkWriter::new(Vec::new()).finish().unwrap();
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_explicit_iend_written_once() {
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_data(&ChunkType::IEND, &[]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_write_after_iend() {
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_data(&ChunkType::IEND, &[]).unwrap();
+
+        assert!(matches!(writer.write_chunk(&testing_chunk()), Err(PngError::ChunkAfterEnd { .. })));
+    }
+}